@@ -1,11 +1,46 @@
+use sha2::Digest;
 use std::path::Path;
 use syntect::highlighting::ThemeSet;
 
+/// Hash of everything in the themes, which has to match what the engine
+/// computes for the themes it loads from the dump
+fn themes_hash(ts: &ThemeSet) -> anyhow::Result<String> {
+    let json = serde_json::to_vec(ts)?;
+    Ok(format!("{:x}", sha2::Sha256::digest(&json)))
+}
+
 fn main() -> anyhow::Result<()> {
     println!("cargo:rerun-if-changed=themes");
-    let dest_path = Path::new(&std::env::var_os("OUT_DIR").unwrap()).join("themes.themedump");
+    let out_dir = std::env::var_os("OUT_DIR").unwrap();
+    let dest_path = Path::new(&out_dir).join("themes.themedump");
     let mut ts = ThemeSet::new();
     ts.add_from_folder("themes")?;
     syntect::dumps::dump_to_file(&ts, dest_path)?;
+    // Checked against what the dump loads as at runtime,
+    // in case it was made by an incompatible version of syntect
+    std::fs::write(Path::new(&out_dir).join("themes.sha256"), themes_hash(&ts)?)?;
+
+    // Emoji shortcodes, sorted by name for binary search
+    println!("cargo:rerun-if-changed=emoji.txt");
+    let mut emoji = std::fs::read_to_string("emoji.txt")?
+        .lines()
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            let mut parts = l.splitn(2, ' ');
+            let name = parts.next().unwrap().to_string();
+            let emoji = parts.next().unwrap_or_default().trim().to_string();
+            (name, emoji)
+        })
+        .collect::<Vec<_>>();
+    emoji.sort();
+    emoji.dedup_by(|a, b| a.0 == b.0);
+    let table = emoji
+        .iter()
+        .map(|(name, emoji)| format!("({:?}, {:?}),\n", name, emoji))
+        .collect::<String>();
+    std::fs::write(
+        Path::new(&out_dir).join("emoji.rs"),
+        format!("&[\n{}]", table),
+    )?;
     Ok(())
 }
@@ -0,0 +1,103 @@
+/*!
+ * Balancing of the tags in raw HTML embedded in markdown.
+ */
+
+use regex::Regex;
+
+/// Elements that never have a closing tag
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose content is text that can't contain tags
+const RAW_TEXT_TAGS: &[&str] = &["script", "style", "textarea", "title"];
+
+/// Keeps the raw HTML fragments of a page balanced.
+///
+/// Like with the sanitizer, a fragment may open an element that a later one
+/// closes, so the same balancer has to be used for a whole page.
+#[derive(Default, Debug)]
+pub struct TagBalancer {
+    // Elements opened and not closed yet, innermost last
+    open: Vec<String>,
+    // Raw text element we are inside of
+    raw_text: Option<String>,
+}
+
+impl TagBalancer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Balances a raw HTML fragment, returning it and what was wrong with it.
+    ///
+    /// A closing tag closes the elements left open inside of the element
+    /// it closes, and closing tags without an open element are removed.
+    pub fn balance(&mut self, html: &str) -> (String, Vec<String>) {
+        let r = Regex::new(
+            r#"(?s)<!--.*?-->|<(/?)([a-zA-Z][a-zA-Z0-9-]*)((?:[^>"']|"[^"]*"|'[^']*')*)>"#,
+        )
+        .unwrap();
+        let mut out = String::new();
+        let mut problems = Vec::new();
+        let mut last = 0;
+        for caps in r.captures_iter(html) {
+            let m = caps.get(0).unwrap();
+            let name = match caps.get(2) {
+                Some(name) => name.as_str().to_ascii_lowercase(),
+                // A comment
+                None => continue,
+            };
+            let closing = !caps[1].is_empty();
+            if let Some(raw_text) = &self.raw_text {
+                if closing && *raw_text == name {
+                    self.raw_text = None;
+                }
+                continue;
+            }
+            if !closing {
+                let self_closing = caps[3].trim_end().ends_with('/');
+                if RAW_TEXT_TAGS.contains(&name.as_str()) && !self_closing {
+                    self.raw_text = Some(name);
+                } else if !VOID_TAGS.contains(&name.as_str()) && !self_closing {
+                    self.open.push(name);
+                }
+                continue;
+            }
+            out.push_str(&html[last..m.start()]);
+            last = m.end();
+            match self.open.iter().rposition(|open| *open == name) {
+                Some(i) => {
+                    for unclosed in self.open.drain(i..).skip(1).rev() {
+                        problems.push(format!("<{}> is never closed", unclosed));
+                        out.push_str(&format!("</{}>", unclosed));
+                    }
+                    out.push_str(m.as_str());
+                }
+                None if VOID_TAGS.contains(&name.as_str()) => {
+                    problems.push(format!("</{}> closes a void element", name))
+                }
+                None => problems.push(format!("</{}> closes nothing", name)),
+            }
+        }
+        out.push_str(&html[last..]);
+        (out, problems)
+    }
+
+    /// Closes the elements still open at the end of the page,
+    /// returning the closing tags and what was wrong.
+    pub fn finish(&mut self) -> (String, Vec<String>) {
+        let mut out = String::new();
+        let mut problems = Vec::new();
+        if let Some(raw_text) = self.raw_text.take() {
+            problems.push(format!("<{}> is never closed", raw_text));
+            out.push_str(&format!("</{}>", raw_text));
+        }
+        for unclosed in self.open.drain(..).rev() {
+            problems.push(format!("<{}> is never closed", unclosed));
+            out.push_str(&format!("</{}>", unclosed));
+        }
+        (out, problems)
+    }
+}
@@ -3,9 +3,13 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use pulldown_cmark::Options;
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
 
-use crate::util::PathHelper;
+use crate::error::{EngineError, Result};
+use crate::toc::DEFAULT_TOC_TITLE;
+use crate::util::{to_url_path, PathHelper};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
@@ -16,10 +20,109 @@ pub struct Config {
     pub inputs: Option<InputsConfig>,
     // Lib config
     pub lib: Option<LibConfig>,
+    // Generated outputs config
+    pub outputs: Option<OutputsConfig>,
+    // Build behavior config
+    pub build: Option<BuildConfig>,
+    // Markdown parsing config
+    pub markdown: Option<MarkdownConfig>,
     /// Theme to use for syntax highlighting.
     ///
     /// Defaults to "Visual Studio Code Dark+" (built-in).
     pub theme: Option<String>,
+    /// How highlighted code is colored
+    ///
+    /// If none, defaults to inline styles.
+    pub highlight_style: Option<HighlightStyle>,
+    /// Path prefix the site is served under, e.g. "/myproject"
+    ///
+    /// If none, the site is assumed to be served from the root.
+    pub base_path: Option<String>,
+    /// Origin the site is deployed to, e.g. "https://example.com"
+    ///
+    /// Used for absolute URLs (e.g. social cards).
+    /// If none, absolute URLs are not generated.
+    pub base_url: Option<String>,
+    /// Heading of the table of contents
+    ///
+    /// If none, defaults to "Table of contents".
+    pub toc_title: Option<String>,
+    /// Accessibility checks (e.g. images without alt text)
+    ///
+    /// If none, defaults to off.
+    pub accessibility_lint: Option<AccessibilityLint>,
+    /// Whether images get `loading="lazy"` and `decoding="async"`
+    ///
+    /// If none, defaults to true.
+    pub lazy_load_images: Option<bool>,
+    /// Whether the first image of a page is loaded eagerly
+    /// (useful for images above the fold)
+    ///
+    /// If none, defaults to false.
+    pub eager_first_image: Option<bool>,
+    /// Whether images also get a PNG or JPEG version
+    /// for clients without WebP support
+    ///
+    /// If none, defaults to false.
+    pub image_fallback: Option<bool>,
+    /// Whether `:shortcode:`s are replaced with emoji
+    ///
+    /// If none, defaults to false.
+    pub emoji: Option<bool>,
+    /// Reading speed used to estimate the time to read, in words per minute
+    ///
+    /// If none, defaults to 200.
+    pub reading_wpm: Option<u32>,
+    /// Estimated time to read, with `{}` replaced by the number of minutes
+    ///
+    /// If none, defaults to "{} min read".
+    pub ttr_format: Option<String>,
+    /// Longest generated excerpt, in characters
+    ///
+    /// If none, defaults to 200.
+    pub excerpt_length: Option<usize>,
+    /// Whether header slugs are romanized to ASCII (e.g. "Café" to "cafe")
+    ///
+    /// If none, defaults to false.
+    pub slug_transliterate: Option<bool>,
+    /// Language of pages that don't set their own, e.g. "en"
+    ///
+    /// If none, defaults to "en".
+    pub default_lang: Option<String>,
+}
+
+/// How accessibility problems are reported
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccessibilityLint {
+    /// Not checked
+    Off,
+    /// Logged as warnings
+    Warn,
+    /// Logged as warnings and fail the build
+    Strict,
+}
+
+/// What happens to pages and stylesheets smaller than `min-output-size`
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum SmallOutputs {
+    /// Written without checking
+    Off,
+    /// Written and logged as warnings
+    Warn,
+    /// Fail instead of being written
+    Error,
+}
+
+/// How highlighted code is colored
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum HighlightStyle {
+    /// A `style` attribute on every token
+    Inline,
+    /// Classes on every token, colored by a stylesheet per theme
+    Classes,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -31,11 +134,67 @@ pub struct ResolvedConfig {
     pub inputs: ResolvedInputsConfig,
     // Lib config
     pub lib: ResolvedLibConfig,
+    // Generated outputs config
+    pub outputs: ResolvedOutputsConfig,
+    // Build behavior config
+    pub build: ResolvedBuildConfig,
+    // Markdown parsing config
+    pub markdown: ResolvedMarkdownConfig,
     /// Theme to use for syntax highlighting.
     pub theme: String,
+    /// How highlighted code is colored
+    pub highlight_style: HighlightStyle,
+    /// Path prefix the site is served under, without a trailing slash
+    /// (empty when served from the root)
+    pub base_path: String,
+    /// Origin the site is deployed to, without a trailing slash
+    pub base_url: Option<String>,
+    /// Heading of the table of contents (not yet escaped)
+    pub toc_title: String,
+    /// Accessibility checks
+    pub accessibility_lint: AccessibilityLint,
+    /// Whether images get `loading="lazy"` and `decoding="async"`
+    pub lazy_load_images: bool,
+    /// Whether the first image of a page is loaded eagerly
+    pub eager_first_image: bool,
+    /// Whether images also get a PNG or JPEG version
+    pub image_fallback: bool,
+    /// Whether `:shortcode:`s are replaced with emoji
+    pub emoji: bool,
+    /// Reading speed in words per minute, at least 1
+    pub reading_wpm: u32,
+    /// Estimated time to read, with `{}` replaced by the number of minutes
+    pub ttr_format: String,
+    /// Longest generated excerpt, in characters
+    pub excerpt_length: usize,
+    /// Whether header slugs are romanized to ASCII
+    pub slug_transliterate: bool,
+    /// Language of pages that don't set their own
+    pub default_lang: String,
 }
 
+/// Default phrasing of the estimated time to read
+pub const DEFAULT_TTR_FORMAT: &str = "{} min read";
+
 impl Config {
+    /// Parses a config from several TOML documents.
+    ///
+    /// Tables are merged recursively, and any other value in a later document
+    /// overrides the one in an earlier document.
+    /// `${VAR}` in string values is replaced with the environment variable `VAR`.
+    pub fn from_layers<S: AsRef<str>>(layers: &[S]) -> Result<Config> {
+        let mut merged = toml::Value::Table(Default::default());
+        for layer in layers {
+            let mut value = toml::from_str::<toml::Value>(layer.as_ref())
+                .map_err(|e| EngineError::Config(e.to_string()))?;
+            expand_env_vars(&mut value)?;
+            merge_toml(&mut merged, value);
+        }
+        merged
+            .try_into()
+            .map_err(|e| EngineError::Config(e.to_string()))
+    }
+
     pub fn resolve(self, config_folder: &Path) -> ResolvedConfig {
         let roots = self.roots.resolve(config_folder);
         let inputs = self
@@ -46,12 +205,144 @@ impl Config {
             .lib
             .unwrap_or_default()
             .resolve(&roots.lib, config_folder);
+        let outputs = self.outputs.unwrap_or_default().resolve(config_folder);
+        let build = self.build.unwrap_or_default().resolve();
+        let markdown = self.markdown.unwrap_or_default().resolve();
         ResolvedConfig {
             roots,
             inputs,
             lib,
+            outputs,
+            build,
+            markdown,
             theme: self.theme.unwrap_or_else(|| "Monokai".to_string()),
+            highlight_style: self.highlight_style.unwrap_or(HighlightStyle::Inline),
+            base_path: self
+                .base_path
+                .map(|p| {
+                    let p = p.trim_matches('/');
+                    if p.is_empty() {
+                        String::new()
+                    } else {
+                        format!("/{}", p)
+                    }
+                })
+                .unwrap_or_default(),
+            base_url: self.base_url.map(|u| u.trim_end_matches('/').to_string()),
+            toc_title: self
+                .toc_title
+                .unwrap_or_else(|| DEFAULT_TOC_TITLE.to_string()),
+            accessibility_lint: self.accessibility_lint.unwrap_or(AccessibilityLint::Off),
+            lazy_load_images: self.lazy_load_images.unwrap_or(true),
+            eager_first_image: self.eager_first_image.unwrap_or(false),
+            image_fallback: self.image_fallback.unwrap_or(false),
+            emoji: self.emoji.unwrap_or(false),
+            reading_wpm: self.reading_wpm.unwrap_or(200).max(1),
+            ttr_format: self
+                .ttr_format
+                .unwrap_or_else(|| DEFAULT_TTR_FORMAT.to_string()),
+            excerpt_length: self.excerpt_length.unwrap_or(200),
+            slug_transliterate: self.slug_transliterate.unwrap_or(false),
+            default_lang: self.default_lang.unwrap_or_else(|| "en".to_string()),
+        }
+    }
+}
+
+/// Replaces `${VAR}` in every string in `value` with the environment variable `VAR`.
+///
+/// Fails if a referenced variable is unset.
+fn expand_env_vars(value: &mut toml::Value) -> Result<()> {
+    match value {
+        toml::Value::String(s) => {
+            let r = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+            let mut missing = None;
+            let expanded = r.replace_all(s, |caps: &Captures| {
+                std::env::var(&caps[1]).unwrap_or_else(|_| {
+                    missing.get_or_insert_with(|| caps[1].to_string());
+                    String::new()
+                })
+            });
+            if let Some(name) = missing {
+                return Err(EngineError::Config(format!(
+                    "environment variable {} is not set",
+                    name
+                )));
+            }
+            *s = expanded.into_owned();
+        }
+        toml::Value::Array(values) => {
+            for v in values {
+                expand_env_vars(v)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, v) in table.iter_mut() {
+                expand_env_vars(v)?;
+            }
         }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Merges `overlay` into `base`, see `Config::from_layers`
+fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (k, v) in overlay {
+                match base.get_mut(&k) {
+                    Some(existing) => merge_toml(existing, v),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+impl ResolvedConfig {
+    /// Turns a path relative to the output root into an absolute URL path,
+    /// taking the base path into account.
+    pub fn site_url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_path, path.trim_start_matches('/'))
+    }
+
+    /// Output path of a page, relative to the output root,
+    /// from its source path relative to the source root.
+    ///
+    /// With pretty URLs, pages other than `index` get a folder of their own.
+    pub fn page_output_path(&self, source: &Path) -> PathBuf {
+        if self.outputs.pretty_urls && source.file_stem() != Some("index".as_ref()) {
+            source.with_extension("").join("index.html")
+        } else {
+            source.with_extension("html")
+        }
+    }
+
+    /// URL path of a page, relative to the output root,
+    /// from its output path relative to the output root.
+    ///
+    /// With pretty URLs, `index.html` is left out.
+    pub fn page_url_path(&self, output: &Path) -> String {
+        let path = to_url_path(output);
+        if !self.outputs.pretty_urls {
+            return path;
+        }
+        match path.strip_suffix("index.html") {
+            Some(folder) if folder.is_empty() || folder.ends_with('/') => folder.to_string(),
+            _ => path,
+        }
+    }
+
+    /// Like `site_url`, but including the base URL.
+    ///
+    /// Returns none if no base URL is configured.
+    pub fn absolute_url(&self, path: &str) -> Option<String> {
+        self.base_url
+            .as_ref()
+            .map(|base| format!("{}{}", base, self.site_url(path)))
     }
 }
 
@@ -66,6 +357,11 @@ pub struct RootsConfig {
     pub assets: PathBuf,
     /// Output root
     pub output: PathBuf,
+    /// Images root, where relative image paths are looked up
+    /// if they aren't next to the page
+    ///
+    /// If none, defaults to the images folder in the assets root
+    pub images: Option<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -79,20 +375,27 @@ pub struct ResolvedRootsConfig {
     pub assets: PathBuf,
     /// Output root
     pub output: PathBuf,
+    /// Images root
+    pub images: PathBuf,
 }
 
 impl RootsConfig {
     pub fn resolve(self, config_location: &Path) -> ResolvedRootsConfig {
+        let assets = self
+            .assets
+            .maybe_suffix(config_location)
+            .maybe_canonicalize();
         ResolvedRootsConfig {
             source: self
                 .source
                 .maybe_suffix(config_location)
                 .maybe_canonicalize(),
             lib: self.lib.maybe_suffix(config_location).maybe_canonicalize(),
-            assets: self
-                .assets
-                .maybe_suffix(config_location)
-                .maybe_canonicalize(),
+            images: self
+                .images
+                .map(|p| p.maybe_suffix(config_location).maybe_canonicalize())
+                .unwrap_or_else(|| assets.join("images")),
+            assets,
             output: self
                 .output
                 .maybe_suffix(config_location)
@@ -112,6 +415,26 @@ pub struct InputsConfig {
     ///
     /// If none, defaults to the _keep file in the source root
     pub keep: Option<PathBuf>,
+    /// Not found page, rendered to 404.html
+    ///
+    /// If none, defaults to the 404.md file in the source root
+    pub not_found: Option<PathBuf>,
+    /// Extensions of markdown source files, in lookup order
+    ///
+    /// If none, defaults to just "md"
+    pub extensions: Option<Vec<String>>,
+    /// Render every source file, not just the ones reachable by links
+    ///
+    /// If none, defaults to false
+    pub build_all: Option<bool>,
+    /// Glob patterns, relative to the source root, of pages that are always rendered
+    ///
+    /// If none, defaults to no patterns
+    pub always_build: Option<Vec<String>>,
+    /// Glob patterns of source files and folders skipped by `build-all` and `always-build`
+    ///
+    /// If none, defaults to no patterns
+    pub ignore: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -121,6 +444,16 @@ pub struct ResolvedInputsConfig {
     pub index: PathBuf,
     /// Root _keep file
     pub keep: PathBuf,
+    /// Not found page
+    pub not_found: PathBuf,
+    /// Extensions of markdown source files, in lookup order
+    pub extensions: Vec<String>,
+    /// Render every source file, not just the ones reachable by links
+    pub build_all: bool,
+    /// Glob patterns, relative to the source root, of pages that are always rendered
+    pub always_build: Vec<String>,
+    /// Glob patterns of source files and folders skipped by `build-all` and `always-build`
+    pub ignore: Vec<String>,
 }
 
 impl InputsConfig {
@@ -136,6 +469,15 @@ impl InputsConfig {
                 .map(|x| x.maybe_suffix(config_folder))
                 .unwrap_or_else(|| source_root.join("_keep.md"))
                 .maybe_canonicalize(),
+            not_found: self
+                .not_found
+                .map(|x| x.maybe_suffix(config_folder))
+                .unwrap_or_else(|| source_root.join("404.md"))
+                .maybe_canonicalize(),
+            extensions: self.extensions.unwrap_or_else(|| vec!["md".to_string()]),
+            build_all: self.build_all.unwrap_or(false),
+            always_build: self.always_build.unwrap_or_default(),
+            ignore: self.ignore.unwrap_or_default(),
         }
     }
 }
@@ -200,6 +542,10 @@ pub struct StylesConfig {
     /// Note that nonexistent files are ignored and relative paths are resolved
     /// relative to the style chunks root
     pub css: HashMap<String, PathBuf>,
+    /// Style chunks included in every page
+    ///
+    /// If none, defaults to `["_global"]`
+    pub always_styles: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -209,6 +555,8 @@ pub struct ResolvedStylesConfig {
     pub chunks_root: PathBuf,
     /// CSS filenames
     pub css: HashMap<String, PathBuf>,
+    /// Style chunks included in every page
+    pub always_styles: Vec<String>,
 }
 
 impl StylesConfig {
@@ -224,6 +572,280 @@ impl StylesConfig {
                 .into_iter()
                 .map(|(k, v)| (k, v.maybe_suffix(config_folder).maybe_canonicalize()))
                 .collect(),
+            always_styles: self
+                .always_styles
+                .unwrap_or_else(|| vec!["_global".to_string()]),
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct OutputsConfig {
+    /// Whether to write a search-index.json with the text of every page
+    ///
+    /// If none, defaults to false
+    pub generate_search_index: Option<bool>,
+    /// Whether to write a paginated archive of all dated pages to /archive/
+    ///
+    /// If none, defaults to false
+    pub generate_archive: Option<bool>,
+    /// Number of entries per archive page
+    ///
+    /// If none, defaults to 10
+    pub archive_page_size: Option<usize>,
+    /// Template used for archive pages
+    ///
+    /// If none, defaults to the prelude
+    pub archive_template: Option<PathBuf>,
+    /// Whether the style chunks of a page are combined into a single file
+    ///
+    /// If none, defaults to false
+    pub bundle_css: Option<bool>,
+    /// Whether each page gets its own stylesheet without the rules it doesn't use
+    ///
+    /// If none, defaults to false
+    pub purge_css: Option<bool>,
+    /// Class names and ids whose rules are never purged,
+    /// with `*` at the end matching any suffix
+    ///
+    /// If none, defaults to nothing
+    pub purge_css_safelist: Option<Vec<String>>,
+    /// Whether pages are written to `page/index.html` and linked as `page/`
+    /// instead of `page.html`
+    ///
+    /// If none, defaults to false
+    pub pretty_urls: Option<bool>,
+    /// Whether to write a robots.txt
+    ///
+    /// If none, defaults to false
+    pub generate_robots: Option<bool>,
+    /// Contents of the robots.txt
+    ///
+    /// If none, defaults to allowing everything
+    pub robots_txt: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ResolvedOutputsConfig {
+    /// Whether to write a search-index.json with the text of every page
+    pub generate_search_index: bool,
+    /// Whether to write a paginated archive of all dated pages to /archive/
+    pub generate_archive: bool,
+    /// Number of entries per archive page
+    pub archive_page_size: usize,
+    /// Template used for archive pages (the prelude if none)
+    pub archive_template: Option<PathBuf>,
+    /// Whether the style chunks of a page are combined into a single file
+    pub bundle_css: bool,
+    /// Whether each page gets its own stylesheet without the rules it doesn't use
+    pub purge_css: bool,
+    /// Class names and ids whose rules are never purged
+    pub purge_css_safelist: Vec<String>,
+    /// Whether pages are written to `page/index.html` and linked as `page/`
+    pub pretty_urls: bool,
+    /// Whether to write a robots.txt
+    pub generate_robots: bool,
+    /// Contents of the robots.txt
+    pub robots_txt: String,
+}
+
+/// Default robots.txt, allowing everything
+pub const DEFAULT_ROBOTS_TXT: &str = "User-agent: *\nDisallow:\n";
+
+impl OutputsConfig {
+    pub fn resolve(self, config_folder: &Path) -> ResolvedOutputsConfig {
+        ResolvedOutputsConfig {
+            generate_search_index: self.generate_search_index.unwrap_or(false),
+            generate_archive: self.generate_archive.unwrap_or(false),
+            archive_page_size: self.archive_page_size.unwrap_or(10).max(1),
+            archive_template: self
+                .archive_template
+                .map(|x| x.maybe_suffix(config_folder).maybe_canonicalize()),
+            bundle_css: self.bundle_css.unwrap_or(false),
+            purge_css: self.purge_css.unwrap_or(false),
+            purge_css_safelist: self.purge_css_safelist.unwrap_or_default(),
+            pretty_urls: self.pretty_urls.unwrap_or(false),
+            generate_robots: self.generate_robots.unwrap_or(false),
+            robots_txt: self
+                .robots_txt
+                .unwrap_or_else(|| DEFAULT_ROBOTS_TXT.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildConfig {
+    /// Keep building when an input fails, counting it as an error
+    ///
+    /// If none, defaults to false (the first failure stops the build)
+    pub keep_going: Option<bool>,
+    /// Seconds to wait for a remote asset to download
+    ///
+    /// If none, defaults to 30
+    pub fetch_timeout: Option<u64>,
+    /// How many times a remote asset is downloaded again
+    /// after a connection error, timeout or server error
+    ///
+    /// If none, defaults to 3
+    pub fetch_retries: Option<u32>,
+    /// What happens to pages and stylesheets below `min-output-size`
+    ///
+    /// If none, defaults to off
+    pub small_outputs: Option<SmallOutputs>,
+    /// Size in bytes below which a page or stylesheet is suspicious
+    ///
+    /// If none, defaults to 1 (only empty outputs)
+    pub min_output_size: Option<usize>,
+    /// Also write every page before it is minified, as `{name}.debug.html`
+    ///
+    /// If none, defaults to false
+    pub emit_unminified: Option<bool>,
+    /// Build in a copy of the output root that only replaces it on success
+    ///
+    /// If none, defaults to false
+    pub atomic: Option<bool>,
+    /// How many remote assets are downloaded at once
+    ///
+    /// If none, defaults to 16
+    pub fetch_concurrency: Option<usize>,
+    /// How many images are decoded and encoded at once
+    ///
+    /// If none, defaults to the number of CPUs
+    pub encode_concurrency: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ResolvedBuildConfig {
+    /// Keep building when an input fails, counting it as an error
+    pub keep_going: bool,
+    /// Seconds to wait for a remote asset to download
+    pub fetch_timeout: u64,
+    /// How many times a remote asset is downloaded again after a transient error
+    pub fetch_retries: u32,
+    /// What happens to pages and stylesheets below `min_output_size`
+    pub small_outputs: SmallOutputs,
+    /// Size in bytes below which a page or stylesheet is suspicious
+    pub min_output_size: usize,
+    /// Also write every page before it is minified
+    pub emit_unminified: bool,
+    /// Build in a copy of the output root that only replaces it on success
+    pub atomic: bool,
+    /// How many remote assets are downloaded at once (at least 1)
+    pub fetch_concurrency: usize,
+    /// How many images are decoded and encoded at once (at least 1)
+    pub encode_concurrency: usize,
+}
+
+/// Default seconds to wait for a remote asset to download
+pub const DEFAULT_FETCH_TIMEOUT: u64 = 30;
+
+impl BuildConfig {
+    pub fn resolve(self) -> ResolvedBuildConfig {
+        ResolvedBuildConfig {
+            keep_going: self.keep_going.unwrap_or(false),
+            fetch_timeout: self.fetch_timeout.unwrap_or(DEFAULT_FETCH_TIMEOUT),
+            fetch_retries: self.fetch_retries.unwrap_or(3),
+            small_outputs: self.small_outputs.unwrap_or(SmallOutputs::Off),
+            min_output_size: self.min_output_size.unwrap_or(1),
+            emit_unminified: self.emit_unminified.unwrap_or(false),
+            atomic: self.atomic.unwrap_or(false),
+            fetch_concurrency: self.fetch_concurrency.unwrap_or(16).max(1),
+            encode_concurrency: self
+                .encode_concurrency
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+                .max(1),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct MarkdownConfig {
+    /// Turn quotes, dashes and ellipses into their typographic forms
+    ///
+    /// If none, defaults to true
+    pub smart_punctuation: Option<bool>,
+    /// GitHub-style tables
+    ///
+    /// If none, defaults to true
+    pub tables: Option<bool>,
+    /// Footnotes
+    ///
+    /// If none, defaults to true
+    pub footnotes: Option<bool>,
+    /// `~~strikethrough~~`
+    ///
+    /// If none, defaults to true
+    pub strikethrough: Option<bool>,
+    /// `- [ ]` task lists
+    ///
+    /// If none, defaults to true
+    pub tasklists: Option<bool>,
+    /// Pass raw HTML through (otherwise it is escaped and shown as text)
+    ///
+    /// If none, defaults to true
+    pub raw_html: Option<bool>,
+    /// Strip scripts, event handlers and other unsafe markup from raw HTML
+    ///
+    /// If none, defaults to false
+    pub sanitize_html: Option<bool>,
+    /// Close elements raw HTML leaves open and drop stray closing tags, with a warning
+    ///
+    /// If none, defaults to false
+    pub balance_html: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ResolvedMarkdownConfig {
+    /// Turn quotes, dashes and ellipses into their typographic forms
+    pub smart_punctuation: bool,
+    /// GitHub-style tables
+    pub tables: bool,
+    /// Footnotes
+    pub footnotes: bool,
+    /// `~~strikethrough~~`
+    pub strikethrough: bool,
+    /// `- [ ]` task lists
+    pub tasklists: bool,
+    /// Pass raw HTML through
+    pub raw_html: bool,
+    /// Strip scripts, event handlers and other unsafe markup from raw HTML
+    pub sanitize_html: bool,
+    /// Close elements raw HTML leaves open and drop stray closing tags
+    pub balance_html: bool,
+}
+
+impl MarkdownConfig {
+    pub fn resolve(self) -> ResolvedMarkdownConfig {
+        ResolvedMarkdownConfig {
+            smart_punctuation: self.smart_punctuation.unwrap_or(true),
+            tables: self.tables.unwrap_or(true),
+            footnotes: self.footnotes.unwrap_or(true),
+            strikethrough: self.strikethrough.unwrap_or(true),
+            tasklists: self.tasklists.unwrap_or(true),
+            raw_html: self.raw_html.unwrap_or(true),
+            sanitize_html: self.sanitize_html.unwrap_or(false),
+            balance_html: self.balance_html.unwrap_or(false),
+        }
+    }
+}
+
+impl ResolvedMarkdownConfig {
+    /// Parser options for these settings
+    ///
+    /// Raw HTML is not a parser option, it is handled while rendering.
+    pub fn options(&self) -> Options {
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_TASKLISTS, self.tasklists);
+        options
+    }
+}
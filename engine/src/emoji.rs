@@ -0,0 +1,33 @@
+/*!
+ * Emoji shortcode (`:rocket:`) expansion.
+ */
+
+use std::borrow::Cow;
+
+use regex::{Captures, Regex};
+
+/// Shortcode names and their emoji, sorted by name
+static EMOJI: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/emoji.rs"));
+
+/// Looks up the emoji for a shortcode name (without colons).
+pub fn lookup(name: &str) -> Option<&'static str> {
+    EMOJI
+        .binary_search_by(|(n, _)| (*n).cmp(name))
+        .ok()
+        .map(|i| EMOJI[i].1)
+}
+
+/// Replaces known `:shortcode:`s in `text` with their emoji.
+///
+/// Unknown shortcodes are left as is.
+pub fn expand(text: &str) -> Cow<'_, str> {
+    if !text.contains(':') {
+        return Cow::Borrowed(text);
+    }
+    let r = Regex::new(r":([a-z0-9_+\-]+):").unwrap();
+    r.replace_all(text, |caps: &Captures| {
+        lookup(&caps[1])
+            .map(str::to_string)
+            .unwrap_or_else(|| caps[0].to_string())
+    })
+}
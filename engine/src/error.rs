@@ -0,0 +1,78 @@
+/*!
+ * Errors surfaced by the engine.
+ */
+
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+/// Everything that can go wrong while building a site
+#[derive(Error, Debug)]
+pub enum EngineError {
+    /// The configuration is invalid or refers to something that doesn't exist
+    #[error("config error: {0}")]
+    Config(String),
+    /// Reading or writing a file failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Fetching a remote asset failed
+    #[error("failed to fetch {url}: {reason}")]
+    Fetch { url: String, reason: String },
+    /// Decoding or encoding an image failed
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    /// The prelude/template is unusable
+    #[error("template error: {0}")]
+    Template(String),
+    /// A page has missing or malformed frontmatter
+    #[error("frontmatter error in {path:?}: {reason}")]
+    Frontmatter { path: PathBuf, reason: String },
+    /// A page failed a strict lint (e.g. an image without alt text)
+    #[error("lint failed in {path:?}: {reason}")]
+    Lint { path: PathBuf, reason: String },
+    /// A page or stylesheet came out smaller than `min-output-size`
+    #[error("{path:?} is only {size} bytes, which is suspiciously small")]
+    SmallOutput { path: PathBuf, size: usize },
+    /// Two inputs would be written to the same output
+    #[error("{first:?} and {second:?} would both be written to {path:?}")]
+    OutputConflict {
+        path: PathBuf,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    /// An input would be written where there is a folder
+    #[error("{input:?} would be written to {path:?}, which is a folder")]
+    OutputIsFolder { path: PathBuf, input: PathBuf },
+    /// Minifying generated HTML or CSS failed
+    #[error("minify failed: {0}")]
+    Minify(String),
+    /// A render task panicked or was cancelled
+    #[error("render task failed: {0}")]
+    Task(String),
+    /// Loading syntax highlighting themes failed
+    #[error(transparent)]
+    Theme(#[from] syntect::LoadingError),
+    /// The themes bundled at build time don't load as the themes that were bundled
+    #[error("bundled themes are unusable (rebuild the engine): {0}")]
+    ThemeDump(String),
+    /// Building an input failed, wrapping why
+    #[error("failed to build {input}")]
+    Failed {
+        input: String,
+        #[source]
+        error: Box<EngineError>,
+    },
+}
+
+impl EngineError {
+    /// Wraps the error with the input it happened while building.
+    pub(crate) fn failed(self, input: impl std::fmt::Display) -> Self {
+        EngineError::Failed {
+            input: input.to_string(),
+            error: Box::new(self),
+        }
+    }
+}
+
+/// Result type used throughout the engine
+pub type Result<T, E = EngineError> = std::result::Result<T, E>;
@@ -0,0 +1,135 @@
+/*!
+ * Footnotes collected from a page and rendered at its end.
+ */
+
+use pulldown_cmark::{escape, html, CowStr, Event};
+
+fn escape_attr(s: &str) -> String {
+    let mut escaped = String::new();
+    escape::escape_html(&mut escaped, s).unwrap();
+    escaped
+}
+
+/// A footnote definition
+#[derive(Debug)]
+struct Definition<'a> {
+    name: String,
+    events: Vec<Event<'a>>,
+}
+
+/// Collects footnote references and definitions of a page
+#[derive(Default, Debug)]
+pub struct Footnotes<'a> {
+    // Footnote names in order of first reference, with their reference counts
+    references: Vec<(String, usize)>,
+    // Definitions in document order
+    definitions: Vec<Definition<'a>>,
+    // Whether we are inside a definition
+    in_definition: bool,
+}
+
+impl<'a> Footnotes<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a reference to a footnote, returning the HTML to put in its place.
+    pub fn reference(&mut self, name: &str) -> String {
+        let (number, count) = match self.references.iter().position(|(n, _)| n == name) {
+            Some(i) => {
+                self.references[i].1 += 1;
+                (i + 1, self.references[i].1)
+            }
+            None => {
+                self.references.push((name.to_string(), 1));
+                (self.references.len(), 1)
+            }
+        };
+        let name = escape_attr(name);
+        format!(
+            r##"<sup class="footnote-ref" id="{}"><a href="#fn-{}">{}</a></sup>"##,
+            ref_id(&name, count),
+            name,
+            number
+        )
+    }
+
+    /// Starts collecting a footnote definition.
+    pub fn start_definition(&mut self, name: &CowStr) {
+        self.in_definition = true;
+        self.definitions.push(Definition {
+            name: name.to_string(),
+            events: Vec::new(),
+        });
+    }
+
+    /// Stops collecting the current footnote definition.
+    pub fn end_definition(&mut self) {
+        self.in_definition = false;
+    }
+
+    /// Whether events are currently part of a definition
+    pub fn in_definition(&self) -> bool {
+        self.in_definition
+    }
+
+    /// Adds an event to the current definition.
+    pub fn push(&mut self, event: Event<'a>) {
+        if let Some(definition) = self.definitions.last_mut() {
+            definition.events.push(event);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    /// Renders the footnotes section, consuming the collected definitions.
+    ///
+    /// Footnotes are numbered in order of their first reference, and
+    /// unreferenced footnotes come last.
+    /// Returns an empty string if there are no definitions.
+    pub fn render(&mut self) -> String {
+        if self.definitions.is_empty() {
+            return String::new();
+        }
+        let mut definitions = std::mem::take(&mut self.definitions);
+        let position = |d: &Definition| {
+            self.references
+                .iter()
+                .position(|(n, _)| n == &d.name)
+                .unwrap_or(usize::MAX)
+        };
+        definitions.sort_by_key(position);
+        let mut s = String::from(r#"<section class="footnotes"><ol>"#);
+        for definition in definitions {
+            let references = self
+                .references
+                .iter()
+                .find(|(n, _)| n == &definition.name)
+                .map(|(_, count)| *count)
+                .unwrap_or(0);
+            let name = escape_attr(&definition.name);
+            s.push_str(&format!(r#"<li id="fn-{}">"#, name));
+            html::push_html(&mut s, definition.events.into_iter());
+            for i in 1..=references {
+                s.push_str(&format!(
+                    r##"<a href="#{}" class="footnote-backref">↩</a>"##,
+                    ref_id(&name, i)
+                ));
+            }
+            s.push_str("</li>");
+        }
+        s.push_str("</ol></section>\n");
+        s
+    }
+}
+
+/// ID of the `count`th reference to a footnote
+fn ref_id(name: &str, count: usize) -> String {
+    if count == 1 {
+        format!("fnref-{}", name)
+    } else {
+        format!("fnref-{}-{}", name, count)
+    }
+}
@@ -2,21 +2,74 @@ use chrono::NaiveDate;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Front matter that can be parsed at the beginning of a Markdown file.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub struct Frontmatter {
     /// Title
     pub title: String,
     /// Date (optional)
     #[serde(
+        default,
         serialize_with = "serialize_date",
         deserialize_with = "deserialize_date"
     )]
     pub date: Option<NaiveDate>,
     /// Estimated time to read (optional)
+    #[serde(default)]
     pub time_to_read: Option<String>,
+    /// Short description of the page, used for social cards (optional)
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Author of the page (optional)
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Cover image URL, used for social cards (optional)
+    #[serde(default)]
+    pub cover: Option<String>,
+    /// Canonical URL, for pages first published elsewhere (optional)
+    #[serde(default)]
+    pub canonical: Option<String>,
+    /// Extra style chunks for this page (optional)
+    #[serde(default)]
+    pub styles: Option<Vec<String>>,
+    /// Scripts for this page, local paths or URLs (optional)
+    #[serde(default)]
+    pub scripts: Option<Vec<String>>,
+    /// Whether the page is written to the output (optional, defaults to true)
+    ///
+    /// Pages that aren't are still rendered for the pages and images they link to.
+    #[serde(default)]
+    pub render: Option<bool>,
+    /// Series the page belongs to, for previous/next links (optional)
+    #[serde(default)]
+    pub series: Option<String>,
+    /// Orders pages with the same date in listings, lower first (optional)
+    #[serde(default)]
+    pub weight: Option<i64>,
+    /// Asks search engines not to index the page (optional, defaults to false)
+    #[serde(default)]
+    pub noindex: Option<bool>,
+    /// Language of the page, e.g. "en" or "ar" (optional),
+    /// defaults to the site's `default-lang`
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// Short plain text summary for listings (optional),
+    /// taken from the first paragraph if not given
+    #[serde(default)]
+    pub excerpt: Option<String>,
+    /// Whether the other pages in the page's folder are listed in place of
+    /// `@@@SLOT_CHILDREN@@@` (optional, defaults to false)
+    #[serde(default, alias = "list-children")]
+    pub list_children: Option<bool>,
+    /// Template for the page instead of the prelude, relative to the lib root
+    /// (optional), whose extension is the page's output extension
+    #[serde(default)]
+    pub layout: Option<String>,
+    /// Any other keys, for the prelude's `@@@IF key @@@` blocks
+    #[serde(flatten)]
+    pub extra: serde_yaml::Mapping,
 }
 
-pub const DATE_FORMAT: &'static str = "%m/%d/%Y";
+pub const DATE_FORMAT: &str = "%m/%d/%Y";
 
 fn serialize_date<S: Serializer>(date: &Option<NaiveDate>, ser: S) -> Result<S::Ok, S::Error> {
     if let Some(date) = date {
@@ -64,7 +117,90 @@ fn deserialize_date<'de, D: Deserializer<'de>>(der: D) -> Result<Option<NaiveDat
 }
 
 impl Frontmatter {
+    /// Whether a key is set to something other than null, false,
+    /// or an empty string, list or mapping.
+    ///
+    /// Keys are the ones written in the frontmatter, e.g. `time_to_read`.
+    pub fn is_truthy(&self, key: &str) -> bool {
+        use serde_yaml::Value;
+        let value = match serde_yaml::to_value(self) {
+            Ok(Value::Mapping(mut m)) => m.remove(&Value::from(key)),
+            _ => None,
+        };
+        match value {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+            Some(Value::String(s)) => !s.is_empty(),
+            Some(Value::Sequence(s)) => !s.is_empty(),
+            Some(Value::Mapping(m)) => !m.is_empty(),
+            Some(_) => true,
+        }
+    }
+
     pub fn parse_from_str(s: &str) -> serde_yaml::Result<Self> {
         serde_yaml::from_str(s)
     }
+
+    /// Parses frontmatter on top of some defaults.
+    ///
+    /// Every default is a YAML mapping, and later ones override earlier ones.
+    /// Keys in `s` override all defaults.
+    pub fn parse_with_defaults(
+        s: &str,
+        defaults: &[serde_yaml::Value],
+    ) -> serde_yaml::Result<Self> {
+        use serde_yaml::{Mapping, Value};
+        let mut merged = Mapping::new();
+        for layer in defaults
+            .iter()
+            .chain(std::iter::once(&serde_yaml::from_str(s)?))
+        {
+            if let Value::Mapping(m) = layer {
+                for (k, v) in m {
+                    merged.insert(k.clone(), v.clone());
+                }
+            }
+        }
+        serde_yaml::from_value(Value::Mapping(merged))
+    }
+}
+
+/// Splits the frontmatter off the start of a markdown document.
+///
+/// Frontmatter is fenced by `---` lines, the first of which has to be the
+/// first line of the document (after a byte order mark, if any).
+/// Returns the raw frontmatter, if any, and the rest of the document.
+///
+/// A fence that is never closed is left in the document, and so is one
+/// around something that isn't a YAML mapping, since that is a document
+/// starting with a rule rather than frontmatter.
+/// Invalid YAML is still taken as frontmatter, so the error is reported.
+pub fn split(markdown: &str) -> (Option<&str>, &str) {
+    let doc = markdown.strip_prefix('\u{feff}').unwrap_or(markdown);
+    let mut lines = doc.split_inclusive('\n');
+    let start = match lines.next() {
+        Some(first) if is_fence(first) => first.len(),
+        _ => return (None, markdown),
+    };
+    let mut end = start;
+    for line in lines {
+        if is_fence(line) {
+            let raw = &doc[start..end];
+            return match serde_yaml::from_str(raw) {
+                Ok(serde_yaml::Value::Mapping(_)) | Err(_) => (Some(raw), &doc[end + line.len()..]),
+                Ok(_) => (None, markdown),
+            };
+        }
+        end += line.len();
+    }
+    (None, markdown)
+}
+
+fn is_fence(line: &str) -> bool {
+    line.trim_end() == "---"
+}
+
+/// Parses raw frontmatter on top of `defaults`,
+/// see `Frontmatter::parse_with_defaults`.
+pub fn parse(raw: &str, defaults: &[serde_yaml::Value]) -> serde_yaml::Result<Frontmatter> {
+    Frontmatter::parse_with_defaults(raw, defaults)
 }
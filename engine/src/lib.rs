@@ -1,9 +1,27 @@
 pub mod config;
 pub use config::Config;
 
+pub mod error;
+pub use error::{EngineError, Result};
+
 pub mod process;
-pub use process::Processor;
+pub use process::{
+    expand_frontmatter_blocks, text_direction, truncate_excerpt, Asset, AssetReport, BrokenLink,
+    BuildSummary, CheckReport, InputKind, OutcomeCounts, Processor, Progress, ReadingStats,
+    RenderOutcome,
+};
+// For `Processor::with_themes`
+pub use syntect::highlighting::{Theme, ThemeSet};
+// For `Processor::assets`
+pub use url::Url;
 
-mod frontmatter;
+mod balance;
+mod emoji;
+mod footnotes;
+pub mod frontmatter;
+mod listing;
+pub mod purge;
 mod render_adapter;
-mod util;
+mod sanitize;
+pub mod toc;
+pub mod util;
@@ -0,0 +1,223 @@
+/*!
+ * Generated listings of pages (archives and the like).
+ */
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+use pulldown_cmark::escape;
+
+use crate::frontmatter::{Frontmatter, DATE_FORMAT};
+use crate::process::PageInfo;
+
+/// A single generated listing page
+pub(crate) struct ListingPage {
+    /// Output path relative to the output root
+    pub path: String,
+    pub title: String,
+    /// HTML content
+    pub content: String,
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::new();
+    escape::escape_html(&mut escaped, s).unwrap();
+    escaped
+}
+
+/// Orders posts newest first.
+///
+/// Posts with the same date are ordered by weight, lower first,
+/// with unweighted posts after weighted ones, and then by URL.
+pub(crate) fn newest_first(a: &PageInfo, b: &PageInfo) -> Ordering {
+    let weight = |p: &PageInfo| (p.frontmatter.weight.is_none(), p.frontmatter.weight);
+    b.frontmatter
+        .date
+        .cmp(&a.frontmatter.date)
+        .then_with(|| weight(a).cmp(&weight(b)))
+        .then_with(|| a.url.cmp(&b.url))
+}
+
+/// The pages before and after a page in its series
+#[derive(Default, Debug)]
+pub(crate) struct Neighbours {
+    /// The next older page
+    pub prev: Option<PageInfo>,
+    /// The next newer page
+    pub next: Option<PageInfo>,
+}
+
+/// Finds the previous and next page of every dated page.
+///
+/// Pages are ordered like in the archive, but only among pages of the same
+/// `series` (pages without one form a series of their own).
+/// Undated pages have no neighbours.
+pub(crate) fn neighbours<K: Eq + Hash>(pages: Vec<(K, PageInfo)>) -> HashMap<K, Neighbours> {
+    let mut series = BTreeMap::<_, Vec<_>>::new();
+    for (key, page) in pages {
+        if page.frontmatter.date.is_some() {
+            series
+                .entry(page.frontmatter.series.clone())
+                .or_default()
+                .push((key, page));
+        }
+    }
+    let mut neighbours = HashMap::new();
+    for (_, mut pages) in series {
+        pages.sort_by(|a, b| newest_first(&a.1, &b.1));
+        let infos = pages.iter().map(|p| p.1.clone()).collect::<Vec<_>>();
+        for (i, (key, _)) in pages.into_iter().enumerate() {
+            let prev = infos.get(i + 1).cloned();
+            let next = i.checked_sub(1).map(|j| infos[j].clone());
+            neighbours.insert(key, Neighbours { prev, next });
+        }
+    }
+    neighbours
+}
+
+/// A page's entry in a listing, linking to it with its title and date.
+fn list_item(page: &PageInfo) -> String {
+    let date = page
+        .frontmatter
+        .date
+        .map(|d| d.format(DATE_FORMAT).to_string())
+        .unwrap_or_default();
+    format!(
+        r#"<li><a href="{}">{}</a> <time>{}</time></li>"#,
+        page.url,
+        escape_html(&page.frontmatter.title),
+        date
+    )
+}
+
+/// Lists the pages of a folder, ordered like the archive.
+pub(crate) fn children_list(mut children: Vec<PageInfo>) -> String {
+    children.sort_by(newest_first);
+    let mut content = String::from(r#"<ul class="children">"#);
+    for child in &children {
+        content.push_str(&list_item(child));
+    }
+    content.push_str("</ul>");
+    content
+}
+
+/// A post's card in the post list: its cover, title, date and excerpt.
+fn post_card(post: &PageInfo, cover: Option<&str>) -> String {
+    let date = post
+        .frontmatter
+        .date
+        .map(|d| d.format(DATE_FORMAT).to_string())
+        .unwrap_or_default();
+    let mut card = String::from(r#"<article class="post-card">"#);
+    if let Some(cover) = cover {
+        // the title next to it says what the post is, so no alt text
+        card.push_str(&format!(
+            r#"<a href="{}" tabindex="-1"><img class="post-cover" src="{}" alt="" loading="lazy" decoding="async"></a>"#,
+            post.url, cover
+        ));
+    }
+    card.push_str(&format!(
+        r#"<h2><a href="{}">{}</a></h2><time>{}</time>"#,
+        post.url,
+        escape_html(&post.frontmatter.title),
+        date
+    ));
+    if let Some(excerpt) = &post.frontmatter.excerpt {
+        card.push_str(&format!(
+            r#"<p class="post-excerpt">{}</p>"#,
+            escape_html(excerpt)
+        ));
+    }
+    card.push_str("</article>");
+    card
+}
+
+/// Lists `posts` as cards, newest first.
+///
+/// `cover` gives the URL of a post's cover image, if it has one.
+pub(crate) fn post_cards(
+    posts: &[PageInfo],
+    cover: impl Fn(&PageInfo) -> Option<String>,
+) -> String {
+    let mut posts = posts.iter().collect::<Vec<_>>();
+    posts.sort_by(|a, b| newest_first(a, b));
+    let mut content = String::from(r#"<div class="post-list">"#);
+    for post in posts {
+        content.push_str(&post_card(post, cover(post).as_deref()));
+    }
+    content.push_str("</div>");
+    content
+}
+
+/// The markup of a post list with a single card using every part,
+/// so that purging styles before the list is filled keeps the card styles.
+pub(crate) fn post_cards_skeleton() -> String {
+    let post = PageInfo {
+        url: String::new(),
+        frontmatter: Frontmatter {
+            excerpt: Some(String::new()),
+            ..Default::default()
+        },
+        text: String::new(),
+    };
+    post_cards(&[post], |_| Some(String::new()))
+}
+
+/// Splits `posts` into archive pages of `page_size` entries each.
+///
+/// The first page is written to `archive/index.html`, the rest to
+/// `archive/{n}.html`. `url` turns an output-relative path into a URL.
+pub(crate) fn archive_pages(
+    posts: &[PageInfo],
+    page_size: usize,
+    url: impl Fn(&str) -> String,
+) -> Vec<ListingPage> {
+    let chunks = posts.chunks(page_size).collect::<Vec<_>>();
+    let page_path = |n: usize| {
+        if n == 1 {
+            "archive/index.html".to_string()
+        } else {
+            format!("archive/{}.html", n)
+        }
+    };
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let n = i + 1;
+            let mut content = String::from(r#"<ul class="archive">"#);
+            for post in chunk.iter() {
+                content.push_str(&list_item(post));
+            }
+            content.push_str("</ul>");
+            if chunks.len() > 1 {
+                content.push_str(r#"<nav class="pagination">"#);
+                if n > 1 {
+                    content.push_str(&format!(
+                        r#"<a rel="prev" href="{}">Newer posts</a>"#,
+                        url(&page_path(n - 1))
+                    ));
+                }
+                if n < chunks.len() {
+                    content.push_str(&format!(
+                        r#"<a rel="next" href="{}">Older posts</a>"#,
+                        url(&page_path(n + 1))
+                    ));
+                }
+                content.push_str("</nav>");
+            }
+            ListingPage {
+                path: page_path(n),
+                title: if n == 1 {
+                    "Archive".to_string()
+                } else {
+                    format!("Archive (page {})", n)
+                },
+                content,
+            }
+        })
+        .collect()
+}
@@ -1,9 +1,12 @@
 use anyhow::Context;
 use argh::FromArgs;
-use engine::{Config, Processor};
-use tokio::{fs::File, io::AsyncReadExt};
+use engine::{Config, Processor, Progress};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::time::Duration;
+use tokio::{fs::File, io::AsyncReadExt, sync::watch};
 use tracing::{event, instrument, Level};
-use tracing_subscriber::EnvFilter;
+use tracing_chrome::{ChromeLayerBuilder, TraceStyle};
+use tracing_subscriber::{filter::LevelFilter, prelude::*, EnvFilter};
 
 #[derive(FromArgs)]
 /// A simple site generator :)
@@ -11,38 +14,186 @@ struct Args {
     #[argh(switch)]
     /// forces rebuild
     force: bool,
+    #[argh(switch)]
+    /// keep building when an input fails
+    keep_going: bool,
+    #[argh(switch)]
+    /// exit successfully even if some inputs failed
+    allow_partial: bool,
+    #[argh(switch)]
+    /// also write every page before minification, as page.debug.html
+    emit_unminified: bool,
+    #[argh(switch)]
+    /// build in a copy of the output folder that only replaces it on success
+    atomic: bool,
+    #[argh(switch)]
+    /// read a config from stdin, merged after any config files
+    stdin: bool,
+    #[argh(switch)]
+    /// show a live count of finished inputs instead of logging all but errors
+    progress: bool,
+    #[argh(switch, short = 'v')]
+    /// log more (repeat for even more), ignored if RUST_LOG is set
+    verbose: u8,
+    #[argh(switch, short = 'q')]
+    /// log less (repeat to silence errors too), ignored if RUST_LOG is set
+    quiet: u8,
+    #[argh(option)]
+    /// write a chrome://tracing profile of the build to this file
+    profile: Option<std::path::PathBuf>,
+    #[argh(option)]
+    /// only build this page (relative to the source root) and what it links to
+    page: Option<std::path::PathBuf>,
     #[argh(positional)]
-    /// path to config file
-    config_filename: std::path::PathBuf,
+    /// paths to config files, merged in order (later files override earlier ones)
+    config_filenames: Vec<std::path::PathBuf>,
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Check(Check),
 }
 
+#[derive(FromArgs)]
+#[argh(subcommand, name = "check")]
+/// check links and images without building anything
+struct Check {}
+
 #[instrument]
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = argh::from_env::<Args>();
 
     let format = tracing_subscriber::fmt::format().pretty();
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .event_format(format)
+    // RUST_LOG wins if set, otherwise the level comes from -v/-q
+    let filter = if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        EnvFilter::from_default_env()
+    } else {
+        let mut verbosity = i16::from(args.verbose) - i16::from(args.quiet);
+        if args.progress {
+            // anything more would scroll the progress away
+            verbosity = verbosity.min(-1);
+        }
+        let level = match verbosity {
+            i16::MIN..=-2 => "off",
+            -1 => "error",
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        };
+        EnvFilter::new(level)
+    };
+    // spans are profiled whatever the log level is
+    let (profile, _flush_profile) = match &args.profile {
+        Some(path) => {
+            let (layer, guard) = ChromeLayerBuilder::new()
+                .file(path)
+                .trace_style(TraceStyle::Async)
+                .include_args(true)
+                .build();
+            (Some(layer.with_filter(LevelFilter::INFO)), Some(guard))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .event_format(format)
+                .with_filter(filter),
+        )
+        .with(profile)
         .init();
 
-    event!(Level::INFO, input_filename = ?args.config_filename);
-    let cfg = {
-        let mut f = File::open(&args.config_filename).await?;
+    event!(Level::INFO, input_filenames = ?args.config_filenames);
+    if args.config_filenames.is_empty() && !args.stdin {
+        anyhow::bail!("Either a config file or --stdin is required");
+    }
+    let mut layers = Vec::new();
+    for filename in &args.config_filenames {
         let mut s = String::new();
+        let mut f = File::open(filename)
+            .await
+            .with_context(|| format!("Opening config file {:?}", filename))?;
         f.read_to_string(&mut s).await?;
-        Ok::<_, anyhow::Error>(toml::from_str::<Config>(&s)?)
-    }?
-    .resolve(
-        &args
-            .config_filename
+        layers.push(s);
+    }
+    if args.stdin {
+        let mut s = String::new();
+        tokio::io::stdin().read_to_string(&mut s).await?;
+        layers.push(s);
+    }
+    // Relative paths are resolved against the first config file
+    let config_folder = match args.config_filenames.first() {
+        Some(first) => first
             .parent()
-            .context("Parent folder of config file")?,
-    );
+            .context("Parent folder of config file")?
+            .to_path_buf(),
+        None => std::env::current_dir()?,
+    };
+    let mut cfg = Config::from_layers(&layers)?.resolve(&config_folder);
+    cfg.build.keep_going |= args.keep_going;
+    cfg.build.emit_unminified |= args.emit_unminified;
+    cfg.build.atomic |= args.atomic;
     event!(Level::DEBUG, config = ?cfg);
     let processor = Processor::new(cfg)?;
-    processor.render_toplevel(args.force).await?;
+    if let Some(Command::Check(Check {})) = args.command {
+        let report = processor.check().await?;
+        for broken in &report.broken {
+            event!(Level::ERROR, r#type = "broken_link", page = ?broken.page, reason = %broken.reason);
+        }
+        event!(
+            Level::INFO,
+            r#type = "check",
+            pages = report.pages,
+            broken = report.broken.len()
+        );
+        if !report.broken.is_empty() {
+            anyhow::bail!(
+                "{} broken link(s) in {} page(s)",
+                report.broken.len(),
+                report.pages
+            );
+        }
+        return Ok(());
+    }
+    let bar = args.progress.then(|| show_progress(processor.progress()));
+    let summary = match args.page {
+        Some(page) => processor.render_one(page, args.force).await,
+        None => processor.render_toplevel(args.force).await,
+    };
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    let summary = summary?;
+    if summary.errors > 0 && !args.allow_partial {
+        anyhow::bail!("{} input(s) failed to build", summary.errors);
+    }
 
     Ok(())
 }
+
+/// Shows a spinner with counts of finished inputs until it is cleared.
+fn show_progress(mut progress: watch::Receiver<Progress>) -> ProgressBar {
+    let bar = ProgressBar::new_spinner();
+    bar.set_style(ProgressStyle::with_template("{spinner} {elapsed} {msg}").unwrap());
+    bar.enable_steady_tick(Duration::from_millis(100));
+    let b = bar.clone();
+    tokio::spawn(async move {
+        while progress.changed().await.is_ok() {
+            let p = *progress.borrow();
+            let mut msg = format!(
+                "{} pages, {} images, {} fonts, {} styles, {} scripts done, {} in flight",
+                p.pages, p.images, p.fonts, p.styles, p.scripts, p.in_flight
+            );
+            if p.failed > 0 {
+                msg.push_str(&format!(", {} failed", p.failed));
+            }
+            b.set_message(msg);
+        }
+    });
+    bar
+}
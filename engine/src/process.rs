@@ -3,132 +3,2162 @@
  */
 
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
     io::Cursor,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     pin::Pin,
-    sync::Arc,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::Context;
-use dashmap::DashSet;
-use image::ImageFormat;
-use pulldown_cmark::{html, Options, Parser};
+use dashmap::{DashMap, DashSet};
+use image::{GenericImageView, ImageFormat, ImageOutputFormat};
+use pulldown_cmark::{escape, html, Parser};
 use regex::{Captures, Regex, RegexBuilder};
+use serde::Serialize;
 use surf::Client;
-use syntect::{highlighting::ThemeSet, parsing::SyntaxSet};
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    html::{css_for_theme_with_class_style, ClassStyle},
+    parsing::SyntaxSet,
+};
 use tokio::{
     fs::File,
     io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
-    sync::mpsc::UnboundedSender,
+    sync::{mpsc::UnboundedSender, watch, Semaphore},
 };
-use tokio_util::compat::FuturesAsyncReadCompatExt;
 use tracing::{event, instrument, Level};
 use url::Url;
 
-use crate::config::ResolvedConfig;
-use crate::frontmatter::DATE_FORMAT;
+use crate::config::{AccessibilityLint, ResolvedConfig, SmallOutputs};
+use crate::error::{EngineError, Result};
+use crate::frontmatter::{self, Frontmatter, DATE_FORMAT};
+use crate::listing::{self, Neighbours};
+use crate::purge;
 use crate::render_adapter::{ProcessorContext, RenderAdapter};
+use crate::toc;
+use crate::util::{self, to_url_path, PathHelper};
 
 /// Rendering input
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub(crate) enum RenderingInput {
     Index,
     Keep,
+    NotFound,
     Image {
         input: Url,
-        // Will be output to /images/{output}.webp
+        // Will be output to /images/{output}.{format's extension}
         output: String,
+        format: ImageOutput,
     },
     Font {
         input: Url,
         // Will be output to /fonts/{output}
         output: String,
     },
-    // CSS(chunk_name)
-    Style(&'static str),
+    // CSS(chunk_name), where chunk_name may contain `/`s for subfolders
+    Style(String),
+    // CSS(chunk_names) concatenated into /css/bundle-{hash}.css
+    StyleBundle(Vec<String>),
+    // Script(source path), copied to /js/{hash}-{file name}
+    Script(PathBuf),
     Page(PathBuf),
 }
 
+/// Format an image is written in
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub(crate) enum ImageOutput {
+    WebP,
+    /// Fallback for images that may be transparent
+    Png,
+    /// Fallback for photos
+    Jpeg,
+}
+
+impl ImageOutput {
+    const ALL: [ImageOutput; 3] = [ImageOutput::WebP, ImageOutput::Png, ImageOutput::Jpeg];
+
+    /// Format of the fallback for clients without WebP support,
+    /// going by the image's URL: JPEG for JPEGs and PNG for anything else.
+    fn fallback_for(url: &Url) -> Self {
+        let path = url.path().to_ascii_lowercase();
+        let is_jpeg = if url.scheme() == "data" {
+            path.starts_with("image/jpeg")
+        } else {
+            path.ends_with(".jpg") || path.ends_with(".jpeg")
+        };
+        if is_jpeg {
+            ImageOutput::Jpeg
+        } else {
+            ImageOutput::Png
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ImageOutput::WebP => "webp",
+            ImageOutput::Png => "png",
+            ImageOutput::Jpeg => "jpg",
+        }
+    }
+
+    fn image_format(self) -> ImageFormat {
+        match self {
+            ImageOutput::WebP => ImageFormat::WebP,
+            ImageOutput::Png => ImageFormat::Png,
+            ImageOutput::Jpeg => ImageFormat::Jpeg,
+        }
+    }
+}
+
+impl fmt::Display for RenderingInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderingInput::Index => write!(f, "the index"),
+            RenderingInput::Keep => write!(f, "the keep file"),
+            RenderingInput::NotFound => write!(f, "the not found page"),
+            RenderingInput::Page(path) => write!(f, "page {:?}", path),
+            RenderingInput::Image { input, format, .. } => {
+                // data URLs can be huge, so only show their media type
+                if input.scheme() == "data" {
+                    let media_type = input.path().split([';', ',']).next().unwrap_or("");
+                    write!(f, "image data:{}", media_type)?;
+                } else {
+                    write!(f, "image {}", input)?;
+                }
+                if *format != ImageOutput::WebP {
+                    write!(f, " (as {})", format.extension())?;
+                }
+                Ok(())
+            }
+            RenderingInput::Font { input, .. } => write!(f, "font {}", input),
+            RenderingInput::Style(chunk) => write!(f, "style chunk {:?}", chunk),
+            RenderingInput::StyleBundle(chunks) => write!(f, "style bundle {:?}", chunks),
+            RenderingInput::Script(path) => write!(f, "script {:?}", path),
+        }
+    }
+}
+
+/// Broad category of a rendering input, used for build summaries
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum InputKind {
+    Page,
+    Image,
+    Font,
+    Style,
+    Script,
+}
+
+impl RenderingInput {
+    /// Creates the image input for an image URL,
+    /// returning it along with its URL relative to the output root.
+    ///
+    /// Local files are hashed by their canonical path, so that equivalent
+    /// references end up as the same input.
+    pub(crate) fn image(input: Url) -> (Self, String) {
+        Self::image_as(input, ImageOutput::WebP)
+    }
+
+    /// Like `image`, but for the PNG or JPEG fallback of the image.
+    pub(crate) fn fallback_image(input: Url) -> (Self, String) {
+        let format = ImageOutput::fallback_for(&input);
+        Self::image_as(input, format)
+    }
+
+    fn image_as(input: Url, format: ImageOutput) -> (Self, String) {
+        use sha2::Digest;
+        let input = if input.scheme() == "file" {
+            input
+                .to_file_path()
+                .ok()
+                .and_then(|p| p.canonicalize().ok())
+                .and_then(|p| Url::from_file_path(p).ok())
+                .unwrap_or(input)
+        } else {
+            input
+        };
+        let hashname = format!("{:x}", sha2::Sha256::digest(input.as_str().as_bytes()));
+        let path = format!("images/{}.{}", hashname, format.extension());
+        (
+            RenderingInput::Image {
+                input,
+                output: hashname,
+                format,
+            },
+            path,
+        )
+    }
+
+    pub(crate) fn kind(&self) -> InputKind {
+        match self {
+            RenderingInput::Index
+            | RenderingInput::Keep
+            | RenderingInput::NotFound
+            | RenderingInput::Page(..) => InputKind::Page,
+            RenderingInput::Image { .. } => InputKind::Image,
+            RenderingInput::Font { .. } => InputKind::Font,
+            RenderingInput::Style(..) | RenderingInput::StyleBundle(..) => InputKind::Style,
+            RenderingInput::Script(..) => InputKind::Script,
+        }
+    }
+}
+
+/// What happened when a single input was rendered
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderOutcome {
+    /// Output was (re)written
+    Written,
+    /// Output was already up to date
+    Fresh,
+    /// Nothing was written (missing source, keep file, ...)
+    Skipped,
+    /// Output is written once every page has been rendered
+    Deferred,
+}
+
+/// Written/fresh/skipped counts for one kind of input
+#[derive(Clone, Copy, Default, Debug)]
+pub struct OutcomeCounts {
+    pub written: usize,
+    pub fresh: usize,
+    pub skipped: usize,
+    /// Time spent rendering inputs of this kind, added up over all of them
+    ///
+    /// Inputs render concurrently, so this can be more than the build took.
+    /// Pages also count the time spent waiting for their images.
+    pub time: Duration,
+}
+
+impl OutcomeCounts {
+    fn record(&mut self, outcome: RenderOutcome) {
+        match outcome {
+            RenderOutcome::Written => self.written += 1,
+            RenderOutcome::Fresh => self.fresh += 1,
+            RenderOutcome::Skipped => self.skipped += 1,
+            RenderOutcome::Deferred => {}
+        }
+    }
+}
+
+/// Summary of what a build actually did
+#[derive(Clone, Default, Debug)]
+pub struct BuildSummary {
+    pub pages: OutcomeCounts,
+    pub images: OutcomeCounts,
+    pub fonts: OutcomeCounts,
+    pub styles: OutcomeCounts,
+    pub scripts: OutcomeCounts,
+    /// Number of inputs that failed (only with `keep-going`)
+    pub errors: usize,
+    /// The inputs that failed and why, as `input: error` (only with `keep-going`)
+    pub failed: Vec<String>,
+    /// Total wall-clock time of the build
+    pub elapsed: Duration,
+}
+
+impl OutcomeCounts {
+    fn merge(&mut self, other: &OutcomeCounts) {
+        self.written += other.written;
+        self.fresh += other.fresh;
+        self.skipped += other.skipped;
+        self.time += other.time;
+    }
+}
+
+impl BuildSummary {
+    fn merge(&mut self, other: &BuildSummary) {
+        self.pages.merge(&other.pages);
+        self.images.merge(&other.images);
+        self.fonts.merge(&other.fonts);
+        self.styles.merge(&other.styles);
+        self.scripts.merge(&other.scripts);
+        self.errors += other.errors;
+        self.failed.extend(other.failed.iter().cloned());
+    }
+
+    fn counts_mut(&mut self, kind: InputKind) -> &mut OutcomeCounts {
+        match kind {
+            InputKind::Page => &mut self.pages,
+            InputKind::Image => &mut self.images,
+            InputKind::Font => &mut self.fonts,
+            InputKind::Style => &mut self.styles,
+            InputKind::Script => &mut self.scripts,
+        }
+    }
+
+    fn record(&mut self, kind: InputKind, outcome: RenderOutcome) {
+        self.counts_mut(kind).record(outcome);
+    }
+
+    fn record_failure(&mut self, input: &RenderingInput, error: &EngineError) {
+        event!(Level::ERROR, r#type = "failed", %input, %error);
+        self.errors += 1;
+        self.failed.push(format!("{}: {}", input, error));
+    }
+
+    fn log(&self) {
+        event!(
+            Level::INFO,
+            r#type = "summary",
+            pages_written = self.pages.written,
+            pages_fresh = self.pages.fresh,
+            images_written = self.images.written,
+            images_fresh = self.images.fresh,
+            fonts_written = self.fonts.written,
+            fonts_fresh = self.fonts.fresh,
+            styles_written = self.styles.written,
+            styles_fresh = self.styles.fresh,
+            scripts_written = self.scripts.written,
+            scripts_fresh = self.scripts.fresh,
+            errors = self.errors,
+            time = %self.elapsed.as_secs_f64()
+        );
+        event!(
+            Level::INFO,
+            r#type = "timing",
+            pages = %self.pages.time.as_secs_f64(),
+            images = %self.images.time.as_secs_f64(),
+            fonts = %self.fonts.time.as_secs_f64(),
+            styles = %self.styles.time.as_secs_f64(),
+            scripts = %self.scripts.time.as_secs_f64(),
+            total = %self.elapsed.as_secs_f64()
+        );
+        if !self.failed.is_empty() {
+            event!(
+                Level::ERROR,
+                r#type = "failures",
+                count = self.failed.len(),
+                failed = %self.failed.join("\n")
+            );
+        }
+    }
+}
+
+/// How far a build has got, updated as inputs are found and rendered
+///
+/// The number of inputs grows as pages link to more of them,
+/// so there is no total to compare against.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Progress {
+    /// Inputs that have started rendering
+    pub started: usize,
+    /// Inputs that are rendering right now
+    pub in_flight: usize,
+    /// Finished pages
+    pub pages: usize,
+    /// Finished images
+    pub images: usize,
+    /// Finished fonts
+    pub fonts: usize,
+    /// Finished style chunks and bundles
+    pub styles: usize,
+    /// Finished scripts
+    pub scripts: usize,
+    /// Inputs that failed
+    pub failed: usize,
+}
+
+/// The result of running the markdown pipeline over a page
+pub(crate) struct RenderedMarkdown {
+    /// HTML fragment
+    pub html: String,
+    /// Parsed frontmatter, if any
+    pub frontmatter: Option<Frontmatter>,
+    /// Plain text content (headings and body text)
+    pub text: String,
+    /// Themes of the code blocks, when highlighted with classes
+    pub code_themes: Vec<String>,
+    /// Links and images pointing nowhere, with why
+    pub broken_links: Vec<String>,
+    /// Fragments linked to, with the source of the page they should be in
+    pub anchors: Vec<(PathBuf, String)>,
+}
+
+/// The inputs a processor has rendered, from `Processor::assets`
+///
+/// Each list is ordered by source.
+#[derive(Default, Debug)]
+pub struct AssetReport {
+    /// Pages written, by their source path
+    pub pages: Vec<Asset<PathBuf>>,
+    /// Images, by their URL (`file:` for local ones)
+    pub images: Vec<Asset<Url>>,
+    /// Fonts referenced by stylesheets, by their URL
+    pub fonts: Vec<Asset<Url>>,
+    /// Style chunks, and bundles of them, by the names of their chunks
+    pub styles: Vec<Asset<Vec<String>>>,
+    /// Local scripts, by their source path
+    pub scripts: Vec<Asset<PathBuf>>,
+}
+
+/// An input and where it was written to
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Asset<S> {
+    pub source: S,
+    pub output: PathBuf,
+}
+
+/// Links found broken by `Processor::check`
+#[derive(Default, Debug)]
+pub struct CheckReport {
+    /// Number of pages checked
+    pub pages: usize,
+    /// Broken links, ordered by page
+    pub broken: Vec<BrokenLink>,
+}
+
+/// A link or image that points nowhere
+#[derive(Debug)]
+pub struct BrokenLink {
+    /// Source of the page with the link
+    pub page: PathBuf,
+    /// What is wrong with it
+    pub reason: String,
+}
+
+/// Metadata collected for every published page,
+/// used to generate site-wide outputs after rendering
+#[derive(Clone, Debug)]
+pub(crate) struct PageInfo {
+    /// URL of the page
+    pub url: String,
+    pub frontmatter: Frontmatter,
+    /// Plain text content
+    pub text: String,
+}
+
+/// Length of a page's plain text
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct ReadingStats {
+    /// Number of words
+    pub words: usize,
+    /// Number of characters, not counting whitespace
+    pub characters: usize,
+}
+
+impl ReadingStats {
+    /// Counts the words and characters of some plain text.
+    pub fn of(text: &str) -> Self {
+        Self {
+            words: text.split_whitespace().count(),
+            characters: text.chars().filter(|c| !c.is_whitespace()).count(),
+        }
+    }
+
+    /// Minutes it takes to read the words at `wpm` words per minute,
+    /// rounded up to at least a minute.
+    pub fn minutes(&self, wpm: u32) -> usize {
+        let wpm = wpm.max(1) as usize;
+        self.words.div_ceil(wpm).max(1)
+    }
+
+    /// Fills the `WORD_COUNT` and `CHAR_COUNT` slots of a template.
+    fn fill(&self, html: &str) -> String {
+        html.replace("@@@SLOT_WORD_COUNT@@@", &self.words.to_string())
+            .replace("@@@SLOT_CHAR_COUNT@@@", &self.characters.to_string())
+    }
+}
+
+/// Scripts written right to left, as ISO 15924 codes
+const RTL_SCRIPTS: &[&str] = &[
+    "adlm", "arab", "hebr", "mand", "nkoo", "rohg", "samr", "syrc", "thaa",
+];
+
+/// Languages written right to left by default, as ISO 639 codes
+const RTL_LANGUAGES: &[&str] = &[
+    "ar", "arc", "ckb", "dv", "fa", "he", "iw", "ks", "ps", "sd", "syr", "ug", "ur", "yi",
+];
+
+/// Text direction of a BCP 47 language tag, `"rtl"` or `"ltr"`.
+///
+/// A script subtag decides if there is one, so `ks-Deva` is left to right,
+/// and otherwise the language does.
+pub fn text_direction(lang: &str) -> &'static str {
+    let lang = lang.to_ascii_lowercase();
+    let mut subtags = lang.split(['-', '_']);
+    let language = subtags.next().unwrap_or("");
+    let rtl = match subtags.find(|s| s.len() == 4 && s.chars().all(|c| c.is_ascii_alphabetic())) {
+        Some(script) => RTL_SCRIPTS.contains(&script),
+        None => RTL_LANGUAGES.contains(&language),
+    };
+    if rtl {
+        "rtl"
+    } else {
+        "ltr"
+    }
+}
+
+/// Escapes plain text for use in HTML, including attribute values
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::new();
+    escape::escape_html(&mut escaped, s).unwrap();
+    escaped
+}
+
+/// Separates what an `IF` block shows from what it shows otherwise
+const ELSE_MARKER: &str = "<!-- @@@ELSE@@@ -->";
+
+/// Splits the contents of an `IF` block into what is shown
+/// if its condition holds and what is shown otherwise.
+fn split_else(contents: &str) -> (&str, &str) {
+    contents.split_once(ELSE_MARKER).unwrap_or((contents, ""))
+}
+
+/// Expands `<!-- @@@IF key @@@ -->...<!-- @@@ENDIF@@@ -->` blocks,
+/// keeping their contents if the frontmatter key is truthy,
+/// and `<!-- @@@IFNOT key @@@ -->` blocks, keeping them if it isn't.
+///
+/// Either can have an `<!-- @@@ELSE@@@ -->` part that is kept instead.
+/// Blocks can't be nested.
+pub fn expand_frontmatter_blocks(html: &str, frontmatter: &Frontmatter) -> String {
+    let r = RegexBuilder::new(r#"<!-- @@@IF(NOT)?\s+([\w-]+)\s*@@@ -->(.*?)<!-- @@@ENDIF@@@ -->"#)
+        .dot_matches_new_line(true)
+        .build()
+        .unwrap();
+    r.replace_all(html, |caps: &Captures| {
+        let (then, otherwise) = split_else(caps.get(3).unwrap().as_str());
+        if frontmatter.is_truthy(&caps[2]) != caps.get(1).is_some() {
+            then.to_string()
+        } else {
+            otherwise.to_string()
+        }
+    })
+    .into_owned()
+}
+
+/// Shortens plain text to at most `length` characters,
+/// cutting at a word boundary and ending with an ellipsis if anything was cut.
+pub fn truncate_excerpt(text: &str, length: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= length {
+        return text;
+    }
+    // leave room for the ellipsis
+    let end = text
+        .char_indices()
+        .nth(length.saturating_sub(1))
+        .map_or(text.len(), |(i, _)| i);
+    let cut = &text[..end];
+    // only cut inside a word if it is the first one
+    let cut = match cut.rfind(' ') {
+        Some(space) if !text[end..].starts_with(' ') => &cut[..space],
+        _ => cut,
+    };
+    format!("{}…", cut.trim_end())
+}
+
+/// A page whose output needs every page's metadata,
+/// held back until all pages have been rendered
+#[derive(Debug)]
+struct DeferredPage {
+    /// Source path
+    source: PathBuf,
+    /// Output path
+    out_path: PathBuf,
+    /// Filled template, with the blocks needing other pages left in
+    html: String,
+    /// Whether the output is HTML, which is minified
+    is_html: bool,
+}
+
+/// Entry of the generated search index
+#[derive(Serialize)]
+struct SearchIndexEntry<'a> {
+    title: &'a str,
+    url: &'a str,
+    excerpt: Option<&'a str>,
+    content: &'a str,
+}
+
+/// Sent back to `render_all` by spawned renders
+///
+/// Everything goes through one channel so that inputs discovered by a render
+/// always arrive before that render's result.
+enum RenderMessage {
+    /// A render found another input that has to be rendered
+    Discovered(RenderingInput),
+    /// A render of the given input finished, after the given time
+    Done(RenderingInput, Duration, Result<RenderOutcome>),
+}
+
 /// Processes files
 #[derive(Debug)]
 pub struct Processor {
     /// Stuff is derived from this
     config: ResolvedConfig,
+    // items waiting for the next render_all
+    pending: Mutex<Vec<RenderingInput>>,
     // items that are currently being rendered
     render_stack: DashSet<RenderingInput>,
     // items that have already been rendered
     finished: DashSet<RenderingInput>,
+    // metadata of rendered pages, keyed by source path
+    pages: DashMap<PathBuf, PageInfo>,
+    // pages waiting for write_deferred_pages
+    deferred: Mutex<Vec<DeferredPage>>,
+    // processed style chunks, keyed by source path
+    processed_styles: DashMap<PathBuf, String>,
+    // page templates and when they were modified, keyed by path
+    templates: DashMap<PathBuf, (Option<SystemTime>, String)>,
+    // sizes of processed images, keyed by output hash
+    image_sizes: DashMap<String, ImageSizeSlot>,
+    // source of every page written, keyed by output path
+    claimed_outputs: DashMap<PathBuf, PathBuf>,
+    // hashes of the last written outputs, keyed by source path
+    build_cache: DashMap<PathBuf, String>,
     // request client
     client: Client,
-    // syntax set
-    ss: SyntaxSet,
-    // theme set
-    ts: ThemeSet,
+    // with `atomic`, the output root the staging folder replaces
+    publish_to: Option<PathBuf>,
+    // progress of the build so far, for `progress()`
+    progress_tx: watch::Sender<Progress>,
+    progress_rx: watch::Receiver<Progress>,
+    // limits downloads, which are IO-bound
+    fetch_permits: Semaphore,
+    // limits image decoding and encoding, which are CPU-bound
+    encode_permits: Semaphore,
+    // syntax set, shared by all processors
+    ss: &'static SyntaxSet,
+    // code block theme
+    theme: Theme,
+    // themes from the theme folder, for code blocks asking for another theme
+    extra_themes: ThemeSet,
+    // `inputs.ignore` patterns, and whether they match the whole relative path
+    ignored: Vec<(glob::Pattern, bool)>,
 }
 
-const THEMES: &'static [u8] = include_bytes!(concat!(env!("OUT_DIR"), "/themes.themedump"));
+/// Placeholder for a page's styles while they are purged
+const PURGED_STYLES: &str = "@@@PURGED_STYLES@@@";
+
+/// Slot for the script tags of a page's `scripts`
+const HEAD_SCRIPTS_SLOT: &str = "@@@SLOT_HEAD_SCRIPTS@@@";
+
+/// Slot filled with the list of a page's children, see `Frontmatter::list_children`
+pub(crate) const CHILDREN_SLOT: &str = "@@@SLOT_CHILDREN@@@";
+
+/// Slot filled with cards for every dated page once all pages are rendered
+pub(crate) const POST_LIST_SLOT: &str = "@@@SLOT_POST_LIST@@@";
+
+/// Stands in for the source of archive pages when claiming their outputs
+const ARCHIVE_SOURCE: &str = "<archive>";
+
+/// Format of an image with a MIME type, ignoring its parameters
+fn image_format(content_type: &str) -> Option<ImageFormat> {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    match mime.to_ascii_lowercase().as_str() {
+        "image/webp" => Some(ImageFormat::WebP),
+        "image/png" => Some(ImageFormat::Png),
+        "image/jpeg" => Some(ImageFormat::Jpeg),
+        "image/gif" => Some(ImageFormat::Gif),
+        _ => None,
+    }
+}
+
+/// Media type and contents of a base64 `data:` URL
+fn decode_data_url(url: &Url) -> Result<(String, Vec<u8>)> {
+    let (header, data) = url.path().split_once(',').unwrap_or((url.path(), ""));
+    let media_type = header
+        .strip_suffix(";base64")
+        .ok_or_else(|| EngineError::Fetch {
+            url: format!("data:{}", header),
+            reason: "only base64 data URLs are supported".to_string(),
+        })?;
+    let data = base64::decode(data).map_err(|e| EngineError::Fetch {
+        url: format!("data:{}", header),
+        reason: format!("invalid base64: {}", e),
+    })?;
+    Ok((media_type.to_string(), data))
+}
+
+/// Wait before retrying a failed fetch, doubled after each retry
+const FETCH_BACKOFF: Duration = Duration::from_millis(500);
+
+/// A downloaded remote asset
+struct Fetched {
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Width and height of an image, if known
+type ImageSize = Option<(u32, u32)>;
+
+/// Size of an image once it has been processed
+type ImageSizeSlot = (
+    watch::Sender<Option<ImageSize>>,
+    watch::Receiver<Option<ImageSize>>,
+);
+
+const THEMES: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/themes.themedump"));
+/// SHA-256 of the themes in `THEMES` as JSON, as they were dumped
+const THEMES_HASH: &str = include_str!(concat!(env!("OUT_DIR"), "/themes.sha256"));
+
+/// The default syntaxes, loaded once
+fn default_syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// The bundled themes, loaded once
+///
+/// Fails if the dump doesn't load as exactly the themes that were dumped,
+/// e.g. if it was made by another version of syntect.
+fn bundled_themes() -> Result<&'static ThemeSet> {
+    static THEME_SET: OnceLock<Result<ThemeSet, String>> = OnceLock::new();
+    THEME_SET
+        .get_or_init(|| {
+            let themes: ThemeSet =
+                syntect::dumps::from_reader(THEMES).map_err(|e| e.to_string())?;
+            use sha2::Digest;
+            let json = serde_json::to_vec(&themes).map_err(|e| e.to_string())?;
+            let hash = format!("{:x}", sha2::Sha256::digest(&json));
+            if hash != THEMES_HASH {
+                return Err(format!(
+                    "expected themes with hash {}, found {}",
+                    THEMES_HASH, hash
+                ));
+            }
+            Ok(themes)
+        })
+        .as_ref()
+        .map_err(|e| EngineError::ThemeDump(e.clone()))
+}
+
+/// Looks up a code block theme and its name, in `extra` first.
+///
+/// Names match exactly, or else ignoring case and anything but letters
+/// and digits, so `visual-studio-code-dark` finds `Visual Studio Code Dark+`.
+pub(crate) fn find_theme<'a>(extra: &'a ThemeSet, name: &str) -> Option<(&'a str, &'a Theme)> {
+    let normalize = |s: &str| {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+    // the bundled themes were checked when the processor was made, if needed
+    let sets = std::iter::once(extra)
+        .chain(bundled_themes().ok())
+        .collect::<Vec<_>>();
+    sets.iter()
+        .find_map(|set| set.themes.get_key_value(name))
+        .or_else(|| {
+            let name = normalize(name);
+            sets.iter()
+                .find_map(|set| set.themes.iter().find(|(k, _)| normalize(k) == name))
+        })
+        .map(|(k, theme)| (k.as_str(), theme))
+}
+
+/// Class of the elements holding code highlighted in a theme
+pub(crate) fn syntax_class(theme: &str) -> String {
+    format!("syntax-{}", toc::slugify(theme))
+}
 
 impl Processor {
-    pub fn new(config: ResolvedConfig) -> anyhow::Result<Arc<Self>> {
-        let mut ts = syntect::dumps::from_binary::<ThemeSet>(THEMES);
+    pub fn new(config: ResolvedConfig) -> Result<Arc<Self>> {
+        Self::with_themes(config, ThemeSet::new())
+    }
+
+    /// Like `new`, with extra highlighting themes on top of the ones in the
+    /// themes folder, so themes don't have to be on disk.
+    ///
+    /// Themes can be read from `.tmTheme` bytes with `ThemeSet::load_from_reader`.
+    pub fn with_themes(mut config: ResolvedConfig, themes: ThemeSet) -> Result<Arc<Self>> {
+        // extra themes take precedence over the bundled ones,
+        // which aren't loaded at all if the theme is found there
+        let mut extra = ThemeSet::new();
         if let Some(ref loc) = config.lib.themes_location {
-            ts.add_from_folder(loc)?;
+            extra.add_from_folder(loc)?;
         }
+        extra.themes.extend(themes.themes);
+        let theme = match extra.themes.get(&config.theme) {
+            Some(theme) => theme.clone(),
+            None => match bundled_themes()?.themes.get(&config.theme) {
+                Some(theme) => theme.clone(),
+                None => {
+                    return Err(EngineError::Config(format!(
+                        "unknown theme {:?}",
+                        config.theme
+                    )))
+                }
+            },
+        };
+        let ignored = config
+            .inputs
+            .ignore
+            .iter()
+            .map(|pattern| {
+                let compiled =
+                    glob::Pattern::new(pattern.trim_start_matches('/')).map_err(|e| {
+                        EngineError::Config(format!("invalid ignore pattern {:?}: {}", pattern, e))
+                    })?;
+                Ok((compiled, pattern.contains('/')))
+            })
+            .collect::<Result<_>>()?;
+        let (progress_tx, progress_rx) = watch::channel(Progress::default());
+        // with `atomic`, everything is written to a staging folder
+        let publish_to = if config.build.atomic {
+            let output = config.roots.output.clone();
+            config.roots.output = sibling(&output, "staging");
+            Some(output)
+        } else {
+            None
+        };
+        let fetch_permits = Semaphore::new(config.build.fetch_concurrency);
+        let encode_permits = Semaphore::new(config.build.encode_concurrency);
         Ok(Arc::new(Self {
             config,
+            pending: Default::default(),
             render_stack: Default::default(),
             finished: Default::default(),
+            pages: Default::default(),
+            deferred: Default::default(),
+            processed_styles: Default::default(),
+            templates: Default::default(),
+            image_sizes: Default::default(),
+            claimed_outputs: Default::default(),
+            build_cache: Default::default(),
             client: Client::new(),
-            ss: SyntaxSet::load_defaults_newlines(),
-            ts,
+            progress_tx,
+            progress_rx,
+            fetch_permits,
+            encode_permits,
+            publish_to,
+            ss: default_syntaxes(),
+            theme,
+            extra_themes: extra,
+            ignored,
         }))
     }
 
-    #[instrument(level = Level::INFO, skip(self))]
-    pub async fn render_toplevel(self: Arc<Self>, force: bool) -> anyhow::Result<()> {
-        self.render_stack.insert(RenderingInput::Index);
-        self.render_stack.insert(RenderingInput::Keep);
-        self.render_all(force).await?;
-        Ok(())
+    /// Watches the progress of this processor's builds.
+    ///
+    /// Counts keep adding up over every build the processor runs.
+    pub fn progress(&self) -> watch::Receiver<Progress> {
+        self.progress_rx.clone()
+    }
+
+    /// Lists the inputs rendered by this processor's builds so far,
+    /// with where they were written to.
+    ///
+    /// Pages are only listed if they were written, while other inputs
+    /// are listed even if they failed (with `keep-going`).
+    /// Outputs written per page or at the end of a build (purged styles,
+    /// code themes, the archive and the like) aren't listed.
+    pub fn assets(&self) -> AssetReport {
+        let mut report = AssetReport::default();
+        for claimed in self.claimed_outputs.iter() {
+            report.pages.push(Asset {
+                source: claimed.value().clone(),
+                output: self.published_path(claimed.key()),
+            });
+        }
+        let out_dir = &self.config.roots.output;
+        for input in self.finished.iter() {
+            match &*input {
+                RenderingInput::Image {
+                    input,
+                    output,
+                    format,
+                } => report.images.push(Asset {
+                    source: input.clone(),
+                    output: self.published_path(
+                        &out_dir
+                            .join("images")
+                            .join(output)
+                            .with_extension(format.extension()),
+                    ),
+                }),
+                RenderingInput::Font { input, output } => report.fonts.push(Asset {
+                    source: input.clone(),
+                    output: self.published_path(&out_dir.join("fonts").join(output)),
+                }),
+                RenderingInput::Style(sname) => {
+                    if let Some((_, out_path)) = self.style_paths(sname) {
+                        report.styles.push(Asset {
+                            source: vec![sname.clone()],
+                            output: self.published_path(&out_path),
+                        });
+                    }
+                }
+                RenderingInput::StyleBundle(names) => report.styles.push(Asset {
+                    source: names.clone(),
+                    output: self.published_path(&self.style_bundle_path(names)),
+                }),
+                RenderingInput::Script(path) => report.scripts.push(Asset {
+                    source: path.clone(),
+                    output: self.published_path(&self.script_output_path(path)),
+                }),
+                // pages are taken from the outputs they claimed
+                RenderingInput::Index
+                | RenderingInput::Keep
+                | RenderingInput::NotFound
+                | RenderingInput::Page(..) => {}
+            }
+        }
+        report.pages.sort_by(|a, b| a.source.cmp(&b.source));
+        report
+            .images
+            .sort_by(|a, b| (&a.source, &a.output).cmp(&(&b.source, &b.output)));
+        report.fonts.sort_by(|a, b| a.source.cmp(&b.source));
+        report.styles.sort_by(|a, b| a.source.cmp(&b.source));
+        report.scripts.sort_by(|a, b| a.source.cmp(&b.source));
+        report
+    }
+
+    /// Where an output ends up once the build is published,
+    /// which is elsewhere than where it was written with `atomic`.
+    fn published_path(&self, path: &Path) -> PathBuf {
+        match &self.publish_to {
+            Some(output) => output.join(path.maybe_unprefix(&self.config.roots.output)),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Changes the progress and tells its watchers.
+    fn update_progress(&self, f: impl FnOnce(&mut Progress)) {
+        let mut progress = *self.progress_rx.borrow();
+        f(&mut progress);
+        // we hold a receiver, so this can't fail
+        let _ = self.progress_tx.send(progress);
+    }
+
+    /// Builds the whole site.
+    ///
+    /// With `atomic`, the site is built in a copy of the output root
+    /// which only replaces it if nothing failed.
+    pub async fn render_toplevel(self: Arc<Self>, force: bool) -> Result<BuildSummary> {
+        self.stage()?;
+        let r = self.clone().build_toplevel(force).await;
+        self.publish(r)
+    }
+
+    /// Renders a single page and whatever it links to,
+    /// without the index, keep file and site-wide outputs.
+    ///
+    /// `path` is relative to the source root, unless it is absolute.
+    /// The page is rendered again even if this processor already did.
+    /// Its previous and next page links only take pages rendered
+    /// by this processor into account.
+    ///
+    /// With `atomic`, this is built in a copy of the output root
+    /// like `render_toplevel`.
+    pub async fn render_one(self: Arc<Self>, path: PathBuf, force: bool) -> Result<BuildSummary> {
+        self.stage()?;
+        let r = self.clone().build_one(path, force).await;
+        self.publish(r)
+    }
+
+    /// With `atomic`, copies the output root to the staging folder,
+    /// modification times and all so freshness checks work the same.
+    fn stage(&self) -> Result<()> {
+        let output = match &self.publish_to {
+            Some(output) => output,
+            None => return Ok(()),
+        };
+        let staging = &self.config.roots.output;
+        // left over from a build that was interrupted
+        if staging.exists() {
+            std::fs::remove_dir_all(staging)?;
+        }
+        if output.exists() {
+            util::copy_dir_keeping_times(output, staging)?;
+        }
+        Ok(())
+    }
+
+    /// With `atomic`, replaces the output root with the staging folder
+    /// if the build succeeded without errors, or else throws it away.
+    fn publish(&self, r: Result<BuildSummary>) -> Result<BuildSummary> {
+        let output = match &self.publish_to {
+            Some(output) => output,
+            None => return r,
+        };
+        let staging = &self.config.roots.output;
+        match r {
+            Ok(summary) if summary.errors == 0 => {
+                let old = sibling(output, "old");
+                if old.exists() {
+                    std::fs::remove_dir_all(&old)?;
+                }
+                if output.exists() {
+                    std::fs::rename(output, &old)?;
+                }
+                std::fs::rename(staging, output)?;
+                if old.exists() {
+                    std::fs::remove_dir_all(&old)?;
+                }
+                event!(Level::INFO, r#type = "published", path = ?output);
+                Ok(summary)
+            }
+            r => {
+                if staging.exists() {
+                    std::fs::remove_dir_all(staging)?;
+                }
+                event!(Level::WARN, r#type = "not_published", path = ?output);
+                r
+            }
+        }
+    }
+
+    #[instrument(level = Level::INFO, skip(self))]
+    async fn build_toplevel(self: Arc<Self>, force: bool) -> Result<BuildSummary> {
+        let start_time = Instant::now();
+        self.load_build_cache().await;
+        // The index, keep file and 404 page are all optional
+        let inputs = &self.config.inputs;
+        for (input, path) in [
+            (RenderingInput::Index, &inputs.index),
+            (RenderingInput::Keep, &inputs.keep),
+            (RenderingInput::NotFound, &inputs.not_found),
+        ] {
+            if path.exists() {
+                self.enqueue(input);
+            }
+        }
+        if self.config.inputs.build_all {
+            for page in self.source_pages()? {
+                self.enqueue(RenderingInput::Page(page));
+            }
+        } else {
+            for page in self.always_built_pages()? {
+                self.enqueue(RenderingInput::Page(page));
+            }
+        }
+        let mut summary = self.clone().render_all(force).await?;
+        summary.merge(&self.write_deferred_pages(force).await?);
+        if self.config.outputs.generate_search_index {
+            self.write_search_index().await?;
+        }
+        if self.config.outputs.generate_robots {
+            self.write_robots_txt().await?;
+        }
+        if self.config.outputs.generate_archive {
+            let (archive_summary, style_inputs) = self.write_archive(force).await?;
+            summary.merge(&archive_summary);
+            // Styles only used by the archive still need to be rendered
+            for input in style_inputs {
+                self.enqueue(input);
+            }
+            summary.merge(&self.clone().render_all(force).await?);
+        }
+        self.save_build_cache().await?;
+        summary.elapsed = start_time.elapsed();
+        summary.log();
+        Ok(summary)
+    }
+
+    #[instrument(level = Level::INFO, skip(self))]
+    async fn build_one(self: Arc<Self>, path: PathBuf, force: bool) -> Result<BuildSummary> {
+        let start_time = Instant::now();
+        self.load_build_cache().await;
+        let path = path.maybe_suffix(&self.config.roots.source);
+        let path = path
+            .canonicalize()
+            .map_err(|e| std::io::Error::new(e.kind(), format!("{:?}: {}", path, e)))?;
+        let input = RenderingInput::Page(path);
+        self.finished.remove(&input);
+        self.enqueue(input);
+        let mut summary = self.clone().render_all(force).await?;
+        summary.merge(&self.write_deferred_pages(force).await?);
+        self.save_build_cache().await?;
+        summary.elapsed = start_time.elapsed();
+        summary.log();
+        Ok(summary)
+    }
+
+    /// Checks the links of every page without writing anything.
+    ///
+    /// Every page in the source root and every page linked to is run through
+    /// the markdown pipeline, and `hyperref:` links to missing pages, local
+    /// images that don't exist and links to fragments (`#id`) that aren't
+    /// the id of anything in the page or the prelude are reported.
+    /// Remote links and images are not checked.
+    #[instrument(level = Level::INFO, skip(self))]
+    pub async fn check(&self) -> Result<CheckReport> {
+        let inputs = &self.config.inputs;
+        let mut queue = self.source_pages()?;
+        for path in [&inputs.index, &inputs.not_found] {
+            if let Ok(path) = path.canonicalize() {
+                queue.push(path);
+            }
+        }
+        let prelude = self
+            .read_template(&self.config.lib.prelude_location)
+            .await?;
+        let prelude_ids = element_ids(&prelude);
+
+        let mut report = CheckReport::default();
+        let mut ids = HashMap::new();
+        // (page linking, page linked to, fragment)
+        let mut anchors = Vec::new();
+        while let Some(page) = queue.pop() {
+            if ids.contains_key(&page) {
+                continue;
+            }
+            let buf = tokio::fs::read_to_string(&page).await?;
+            let mut styles = HashSet::new();
+            let mut new_stack = Vec::new();
+            let rendered = match self.render_markdown(&page, &buf, &mut styles, &mut new_stack) {
+                Ok(rendered) => rendered,
+                Err(e) => {
+                    report.broken.push(BrokenLink {
+                        page: page.clone(),
+                        reason: e.to_string(),
+                    });
+                    ids.insert(page, HashSet::new());
+                    continue;
+                }
+            };
+            for input in new_stack {
+                if let RenderingInput::Page(linked) = input {
+                    queue.push(linked);
+                }
+            }
+            for reason in rendered.broken_links {
+                report.broken.push(BrokenLink {
+                    page: page.clone(),
+                    reason,
+                });
+            }
+            for (target, fragment) in rendered.anchors {
+                anchors.push((page.clone(), target, fragment));
+            }
+            ids.insert(page, element_ids(&rendered.html));
+        }
+
+        for (page, target, fragment) in anchors {
+            let target = target.canonicalize().unwrap_or(target);
+            let found = prelude_ids.contains(&fragment)
+                || ids.get(&target).is_some_and(|ids| ids.contains(&fragment));
+            if !found {
+                let reason = if target == page {
+                    format!("nothing has the id #{}", fragment)
+                } else {
+                    format!("nothing in {:?} has the id #{}", target, fragment)
+                };
+                report.broken.push(BrokenLink { page, reason });
+            }
+        }
+        report.pages = ids.len();
+        report.broken.sort_by(|a, b| a.page.cmp(&b.page));
+        Ok(report)
+    }
+
+    /// Queues an input for the next `render_all`.
+    fn enqueue(&self, input: RenderingInput) {
+        self.pending.lock().unwrap().push(input);
+    }
+
+    fn spawn_input(
+        self: Arc<Self>,
+        force: bool,
+        input: RenderingInput,
+        tx: UnboundedSender<RenderMessage>,
+    ) {
+        tokio::spawn(async move {
+            let i2 = input.clone();
+            let start_time = Instant::now();
+            // render in its own task so a panic is still reported as done
+            let r = tokio::spawn(self.clone().render(input, force, tx.clone()))
+                .await
+                .unwrap_or_else(|e| Err(EngineError::Task(e.to_string())));
+            let elapsed = start_time.elapsed();
+            // mark as finished first so that it is never in neither set
+            self.finished.insert(i2.clone());
+            self.render_stack.remove(&i2);
+            // render_all may have stopped early because of an error
+            let _ = tx.send(RenderMessage::Done(i2, elapsed, r));
+        });
+    }
+
+    /// Publishes the size of a processed image to pages waiting on it.
+    fn set_image_size(&self, hash: &str, size: ImageSize) {
+        let slot = self
+            .image_sizes
+            .entry(hash.to_string())
+            .or_insert_with(|| watch::channel(None));
+        // a receiver is kept in the slot, so this can't fail
+        let _ = slot.0.send(Some(size));
+    }
+
+    /// Waits until the image with the given output hash has been processed,
+    /// returning its size.
+    async fn image_size(&self, hash: &str) -> ImageSize {
+        let mut rx = self
+            .image_sizes
+            .entry(hash.to_string())
+            .or_insert_with(|| watch::channel(None))
+            .1
+            .clone();
+        loop {
+            if let Some(size) = *rx.borrow() {
+                return size;
+            }
+            if rx.changed().await.is_err() {
+                return None;
+            }
+        }
+    }
+
+    fn build_cache_path(&self) -> PathBuf {
+        self.config.roots.output.join(".build-cache")
+    }
+
+    /// Loads the hashes of previously written outputs.
+    ///
+    /// A missing or unreadable cache is treated as empty.
+    async fn load_build_cache(&self) {
+        let path = self.build_cache_path();
+        let cache = match tokio::fs::read(&path).await {
+            Ok(s) => s,
+            Err(_) => return,
+        };
+        match serde_json::from_slice::<HashMap<PathBuf, String>>(&cache) {
+            Ok(cache) => {
+                for (source, hash) in cache {
+                    self.build_cache.insert(source, hash);
+                }
+            }
+            Err(e) => event!(Level::WARN, r#type = "invalid_build_cache", ?path, %e),
+        }
+    }
+
+    /// Saves the hashes of written outputs for the next build.
+    async fn save_build_cache(&self) -> Result<()> {
+        let cache = self
+            .build_cache
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect::<BTreeMap<_, _>>();
+        tokio::fs::create_dir_all(&self.config.roots.output).await?;
+        tokio::fs::write(
+            self.build_cache_path(),
+            serde_json::to_string(&cache).unwrap(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Records that a page's source is written to `out_path`,
+    /// failing if another source already is.
+    fn claim_output(&self, out_path: &Path, source: &Path) -> Result<()> {
+        use dashmap::mapref::entry::Entry;
+        match self.claimed_outputs.entry(out_path.to_path_buf()) {
+            Entry::Occupied(e) if e.get() != source => Err(EngineError::OutputConflict {
+                path: out_path.to_path_buf(),
+                first: e.get().clone(),
+                second: source.to_path_buf(),
+            }),
+            Entry::Occupied(_) => Ok(()),
+            Entry::Vacant(e) => {
+                e.insert(source.to_path_buf());
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks a page or stylesheet about to be written against `min-output-size`,
+    /// since a legitimate one is essentially never empty.
+    fn check_output_size(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        let build = &self.config.build;
+        if contents.len() >= build.min_output_size {
+            return Ok(());
+        }
+        match build.small_outputs {
+            SmallOutputs::Off => Ok(()),
+            SmallOutputs::Warn => {
+                event!(
+                    Level::WARN,
+                    r#type = "small_output",
+                    ?path,
+                    size = contents.len()
+                );
+                Ok(())
+            }
+            SmallOutputs::Error => Err(EngineError::SmallOutput {
+                path: path.to_path_buf(),
+                size: contents.len(),
+            }),
+        }
+    }
+
+    /// Writes an output file, creating its parents.
+    ///
+    /// Unless the rebuild is forced, nothing is written if the output already
+    /// has the same contents, according to the build cache or (if the
+    /// freshness is unknown) the output itself.
+    async fn write_output(
+        &self,
+        source: &Path,
+        path: &Path,
+        contents: &[u8],
+        freshness: Freshness,
+    ) -> Result<RenderOutcome> {
+        use sha2::Digest;
+        let hash = format!("{:x}", sha2::Sha256::digest(contents));
+        if freshness != Freshness::Forced && tokio::fs::metadata(path).await.is_ok() {
+            let unchanged =
+                if self.build_cache.get(source).map(|h| h.value() == &hash) == Some(true) {
+                    true
+                } else if freshness == Freshness::Unknown {
+                    tokio::fs::read(path).await.ok().as_deref() == Some(contents)
+                } else {
+                    false
+                };
+            if unchanged {
+                event!(Level::INFO, r#type = "unchanged", ?path);
+                self.build_cache.insert(source.to_path_buf(), hash);
+                return Ok(RenderOutcome::Fresh);
+            }
+        }
+        if let Some(p) = path.parent() {
+            tokio::fs::create_dir_all(p).await?;
+        }
+        if tokio::fs::metadata(path).await.is_ok_and(|m| m.is_dir()) {
+            return Err(EngineError::OutputIsFolder {
+                path: path.to_path_buf(),
+                input: source.to_path_buf(),
+            });
+        }
+        let mut f = File::create(path).await?;
+        f.write_all(contents).await?;
+        self.build_cache.insert(source.to_path_buf(), hash);
+        event!(Level::INFO, r#type = "new", ?path);
+        Ok(RenderOutcome::Written)
+    }
+
+    /// Writes a page's HTML before minification next to it,
+    /// as `{name}.debug.html`, if `emit-unminified` is on.
+    async fn write_unminified(&self, out_path: &Path, html: &str) -> Result<()> {
+        if !self.config.build.emit_unminified {
+            return Ok(());
+        }
+        let path = out_path.with_extension("debug.html");
+        if let Some(p) = path.parent() {
+            tokio::fs::create_dir_all(p).await?;
+        }
+        tokio::fs::write(&path, html).await?;
+        event!(Level::INFO, r#type = "unminified", ?path);
+        Ok(())
+    }
+
+    /// Writes `search-index.json` with the title, URL and text of every page.
+    #[instrument(level = Level::INFO, skip(self))]
+    async fn write_search_index(&self) -> Result<()> {
+        let pages = self
+            .pages
+            .iter()
+            .map(|p| p.value().clone())
+            .collect::<Vec<_>>();
+        let mut entries = pages
+            .iter()
+            .map(|p| SearchIndexEntry {
+                title: &p.frontmatter.title,
+                url: &p.url,
+                excerpt: p.frontmatter.excerpt.as_deref(),
+                content: &p.text,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.url.cmp(b.url));
+        let json = serde_json::to_string(&entries).unwrap();
+        let out_path = self.config.roots.output.join("search-index.json");
+        tokio::fs::create_dir_all(&self.config.roots.output).await?;
+        tokio::fs::write(&out_path, json).await?;
+        event!(Level::INFO, r#type = "new", path = ?out_path, pages = entries.len());
+        Ok(())
+    }
+
+    /// Writes the robots.txt to the output root.
+    async fn write_robots_txt(&self) -> Result<()> {
+        let out_path = self.config.roots.output.join("robots.txt");
+        tokio::fs::create_dir_all(&self.config.roots.output).await?;
+        tokio::fs::write(&out_path, &self.config.outputs.robots_txt).await?;
+        event!(Level::INFO, r#type = "new", path = ?out_path);
+        Ok(())
+    }
+
+    /// Reads a page template, making sure it has a content slot.
+    ///
+    /// Templates are only read again if they were modified since.
+    async fn read_template(&self, path: &Path) -> Result<String> {
+        let modified = tokio::fs::metadata(path).await?.modified().ok();
+        if let Some(cached) = self.templates.get(path) {
+            if modified.is_some() && cached.0 == modified {
+                return Ok(cached.1.clone());
+            }
+        }
+        let template = {
+            let mut f = File::open(path).await?;
+            let mut s = String::new();
+            f.read_to_string(&mut s).await?;
+            Ok::<_, std::io::Error>(s)
+        }?;
+        if !template.contains("@@@SLOT_CONTENT@@@") {
+            return Err(EngineError::Template(format!(
+                "template {:?} has no @@@SLOT_CONTENT@@@ slot",
+                path
+            )));
+        }
+        self.templates
+            .insert(path.to_path_buf(), (modified, template.clone()));
+        Ok(template)
+    }
+
+    /// Builds the `<link>` tags for a page's styles.
+    ///
+    /// Styles without a chunk file are skipped.
+    /// Also returns the style inputs that have to be rendered.
+    fn style_links(&self, styles: &HashSet<&str>) -> (String, Vec<RenderingInput>) {
+        let chunks = self.style_chunks(styles);
+        let outputs = if self.config.outputs.bundle_css && !chunks.is_empty() {
+            let names = chunks.into_iter().map(|c| c.0).collect::<Vec<_>>();
+            let out_path = self.style_bundle_path(&names);
+            vec![(RenderingInput::StyleBundle(names), out_path)]
+        } else {
+            chunks
+                .into_iter()
+                .map(|(sname, _, out_path)| (RenderingInput::Style(sname), out_path))
+                .collect()
+        };
+        let mut links = Vec::new();
+        let mut inputs = Vec::new();
+        for (input, css_out_path) in outputs {
+            inputs.push(input);
+            links.push(self.style_link(&css_out_path));
+        }
+        (format!("\n{}\n", links.join("\n")), inputs)
+    }
+
+    /// Names, source and output paths of the existing chunks for some styles,
+    /// sorted by name.
+    fn style_chunks(&self, styles: &HashSet<&str>) -> Vec<(String, PathBuf, PathBuf)> {
+        // sorted so that the output is the same every time
+        let mut styles = styles.iter().copied().collect::<Vec<_>>();
+        styles.sort_unstable();
+        let mut chunks = Vec::new();
+        for sname in styles {
+            let (path, css_out_path) = match self.style_paths(sname) {
+                Some(paths) => paths,
+                None => {
+                    event!(Level::WARN, r#type = "invalid_style", %sname);
+                    continue;
+                }
+            };
+            // skip missing files
+            if path.canonicalize().is_err() {
+                continue;
+            }
+            chunks.push((sname.to_string(), path, css_out_path));
+        }
+        chunks
+    }
+
+    /// The `<link>` tags for a stylesheet in the output folder.
+    fn style_link(&self, css_out_path: &Path) -> String {
+        let out_dir = &self.config.roots.output;
+        format!(
+            r#"
+    <link rel="preload" href="{0}" as="style" />
+    <link rel="stylesheet" type="text/css" href="{0}" />
+    "#,
+            self.config
+                .site_url(&to_url_path(css_out_path.maybe_unprefix(out_dir)))
+        )
+    }
+
+    /// Writes the stylesheet for code highlighted with classes in a theme,
+    /// returning its path.
+    ///
+    /// Its rules only apply inside elements with the theme's
+    /// `syntax-` class, so several themes can be used on the same page.
+    async fn write_syntax_style(&self, name: &str, force: bool) -> Result<PathBuf> {
+        let theme = match find_theme(&self.extra_themes, name) {
+            Some((_, theme)) => theme,
+            // only known themes are recorded
+            None => &self.theme,
+        };
+        let class = syntax_class(name);
+        let css = css_for_theme_with_class_style(theme, ClassStyle::Spaced)
+            .lines()
+            .map(|line| match line.strip_suffix(" {") {
+                Some(".code") => format!(".{} {{", class),
+                Some(selector) => format!(".{} {} {{", class, selector),
+                None => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let out_path = self
+            .config
+            .roots
+            .output
+            .join("css")
+            .join(format!("{}.css", class));
+        let freshness = if force {
+            Freshness::Forced
+        } else {
+            Freshness::Unknown
+        };
+        // generated, so it is cached by its own path
+        self.write_output(&out_path, &out_path, css.as_bytes(), freshness)
+            .await?;
+        Ok(out_path)
+    }
+
+    /// Writes the page's styles without the rules it doesn't use
+    /// to a file named by a hash of its contents,
+    /// linking to it in place of `PURGED_STYLES` in the page.
+    async fn write_purged_styles(
+        self: Arc<Self>,
+        html: String,
+        chunks: &[(String, PathBuf, PathBuf)],
+        force: bool,
+        tx: &UnboundedSender<RenderMessage>,
+    ) -> Result<String> {
+        use sha2::Digest;
+        // the post list is only filled in later, so purge against a stand-in
+        let used = purge::UsedSelectors::from_html(
+            &html.replace(POST_LIST_SLOT, &listing::post_cards_skeleton()),
+            &self.config.outputs.purge_css_safelist,
+        );
+        let mut css = String::new();
+        for (_, path, _) in chunks {
+            let chunk = self.clone().style_chunk_contents(path, tx.clone()).await?;
+            css.push_str(&purge::purge(&chunk, &used));
+        }
+        let hash = format!("{:x}", sha2::Sha256::digest(css.as_bytes()));
+        let out_path = self
+            .config
+            .roots
+            .output
+            .join("css")
+            .join(format!("page-{}.css", &hash[..16]));
+        let freshness = if force {
+            Freshness::Forced
+        } else {
+            Freshness::Unknown
+        };
+        // named by its contents, so it is cached by its own path
+        self.write_output(&out_path, &out_path, css.as_bytes(), freshness)
+            .await?;
+        Ok(html.replace(PURGED_STYLES, &self.style_link(&out_path)))
+    }
+
+    /// Style chunks included in every page.
+    fn always_styles(&self) -> HashSet<&str> {
+        let always = &self.config.lib.styles.always_styles;
+        always.iter().map(String::as_str).collect()
+    }
+
+    /// Output path of the bundle of the given (sorted) style chunks,
+    /// named by a hash of their names.
+    fn style_bundle_path(&self, names: &[String]) -> PathBuf {
+        use sha2::Digest;
+        let hash = format!("{:x}", sha2::Sha256::digest(names.join("\n").as_bytes()));
+        self.config
+            .roots
+            .output
+            .join("css")
+            .join(format!("bundle-{}.css", &hash[..16]))
+    }
+
+    /// Path of a page layout, relative to the lib root.
+    ///
+    /// Returns none for layouts that would point outside of it.
+    fn layout_path(&self, layout: &str) -> Option<PathBuf> {
+        let relative = Path::new(layout);
+        let valid = relative.components().next().is_some()
+            && relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)));
+        valid.then(|| self.config.roots.lib.join(relative))
+    }
+
+    /// Source and output paths of a style chunk.
+    ///
+    /// Chunk names are paths relative to the chunks root without the `.css`
+    /// extension, separated by `/` (e.g. `blog/code`), and the output keeps
+    /// the same folders under `css/`.
+    /// Returns none for names that would point outside of the chunks root.
+    fn style_paths(&self, sname: &str) -> Option<(PathBuf, PathBuf)> {
+        let components = sname.split('/').collect::<Vec<_>>();
+        let valid = |c: &&str| !c.is_empty() && *c != "." && *c != ".." && !c.contains(['\\', ':']);
+        if !components.iter().all(valid) {
+            return None;
+        }
+        // split always returns at least one component
+        let (name, folders) = components.split_last().unwrap();
+        let relative = folders
+            .iter()
+            .collect::<PathBuf>()
+            .join(format!("{}.css", name));
+        Some((
+            self.config.lib.styles.chunks_root.join(&relative),
+            self.config.roots.output.join("css").join(&relative),
+        ))
+    }
+
+    /// Fills in the slots of a page template.
+    fn fill_template(
+        &self,
+        template: &str,
+        content: &str,
+        styles: &str,
+        frontmatter: &Frontmatter,
+        canonical_url: Option<&str>,
+        og_image: Option<&str>,
+    ) -> String {
+        // before the content goes in, which could contain markers of its own
+        let mut html = expand_frontmatter_blocks(template, frontmatter)
+            .replace("@@@SLOT_STYLES@@@", styles)
+            .replace("@@@SLOT_CONTENT@@@", content)
+            .replace("@@@SLOT_BASE_PATH@@@", &self.config.base_path)
+            .replace("@@@SLOT_TITLE@@@", &escape_html(&frontmatter.title));
+
+        let lang = frontmatter
+            .lang
+            .as_deref()
+            .unwrap_or(&self.config.default_lang);
+        html = html
+            .replace("@@@SLOT_LANG@@@", &escape_html(lang))
+            .replace("@@@SLOT_DIR@@@", text_direction(lang));
+
+        let date = frontmatter.date.map(|d| d.format(DATE_FORMAT).to_string());
+        html = expand_if_block(&html, "DATE", date.as_deref());
+        html = expand_if_block(&html, "TIME_TO_READ", frontmatter.time_to_read.as_deref());
+        // these also end up in attributes, like og:description
+        let description = frontmatter.description.as_deref().map(escape_html);
+        html = expand_if_block(&html, "DESCRIPTION", description.as_deref());
+        let author = frontmatter.author.as_deref().map(escape_html);
+        html = expand_if_block(&html, "AUTHOR", author.as_deref());
+        let excerpt = frontmatter.excerpt.as_deref().map(escape_html);
+        html = expand_if_block(&html, "EXCERPT", excerpt.as_deref());
+        html = expand_if_block(&html, "OG_IMAGE", og_image);
+        html = expand_if_block(&html, "OG_URL", canonical_url);
+        html = expand_if_block(&html, "CANONICAL", canonical_url);
+        let noindex = frontmatter.noindex.unwrap_or(false);
+        html = expand_if_block(&html, "NOINDEX", Some("").filter(|_| noindex));
+
+        html
+    }
+
+    /// Writes the pages held back by `render`,
+    /// now that the previous and next page of each and the list of posts are known.
+    #[instrument(level = Level::INFO, skip(self))]
+    async fn write_deferred_pages(&self, force: bool) -> Result<BuildSummary> {
+        let deferred = std::mem::take(&mut *self.deferred.lock().unwrap());
+        let mut summary = BuildSummary::default();
+        if deferred.is_empty() {
+            return Ok(summary);
+        }
+        let pages = self
+            .pages
+            .iter()
+            .map(|p| (p.key().clone(), p.value().clone()))
+            .collect::<Vec<_>>();
+        let posts = pages
+            .iter()
+            .filter(|p| p.1.frontmatter.date.is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+        let neighbours = listing::neighbours(pages);
+        let start_time = Instant::now();
+        for page in deferred {
+            let post_list = if page.html.contains(POST_LIST_SLOT) {
+                // a dated index shouldn't list itself
+                let others = posts
+                    .iter()
+                    .filter(|p| p.0 != page.source)
+                    .map(|p| p.1.clone())
+                    .collect::<Vec<_>>();
+                listing::post_cards(&others, |post| {
+                    let cover = Url::parse(post.frontmatter.cover.as_deref()?).ok()?;
+                    Some(self.config.site_url(&RenderingInput::image(cover).1))
+                })
+            } else {
+                String::new()
+            };
+            // the index can also be linked to as a page, so keep the entries
+            let n = neighbours.get(&page.source);
+            let html = expand_neighbours(&page.html, n.unwrap_or(&Default::default()));
+            // the slot is usually a paragraph of its own in the page
+            let html = html
+                .replace(&format!("<p>{}</p>", POST_LIST_SLOT), &post_list)
+                .replace(POST_LIST_SLOT, &post_list);
+            let freshness = if force {
+                Freshness::Forced
+            } else {
+                // the neighbours or posts may have changed even if the page didn't
+                Freshness::Unknown
+            };
+            let minified = if page.is_html {
+                self.write_unminified(&page.out_path, &html)
+                    .await
+                    .and_then(|()| minify_html(&html))
+            } else {
+                Ok(html)
+            };
+            let r = match minified {
+                Ok(minified) => match self.check_output_size(&page.out_path, minified.as_bytes()) {
+                    Ok(()) => {
+                        self.write_output(
+                            &page.source,
+                            &page.out_path,
+                            minified.as_bytes(),
+                            freshness,
+                        )
+                        .await
+                    }
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            let input = RenderingInput::Page(page.source.clone());
+            match r {
+                Ok(outcome) => summary.record(InputKind::Page, outcome),
+                Err(e) if self.config.build.keep_going => summary.record_failure(&input, &e),
+                Err(e) => return Err(e.failed(&input)),
+            }
+        }
+        summary.pages.time = start_time.elapsed();
+        Ok(summary)
+    }
+
+    /// Writes the paginated archive of dated pages.
+    ///
+    /// Returns what was written and the style inputs the pages need.
+    #[instrument(level = Level::INFO, skip(self))]
+    async fn write_archive(&self, force: bool) -> Result<(BuildSummary, Vec<RenderingInput>)> {
+        let outputs = &self.config.outputs;
+        let out_dir = &self.config.roots.output;
+        let mut posts = self
+            .pages
+            .iter()
+            .map(|p| p.value().clone())
+            .filter(|p| p.frontmatter.date.is_some())
+            .collect::<Vec<_>>();
+        posts.sort_by(listing::newest_first);
+
+        let template_path = outputs
+            .archive_template
+            .as_ref()
+            .unwrap_or(&self.config.lib.prelude_location);
+        let template = self.read_template(template_path).await?;
+        let mut styles = self.always_styles();
+        styles.insert("archive");
+        styles.insert("link");
+        let (styles, style_inputs) = self.style_links(&styles);
+
+        let pages = listing::archive_pages(&posts, outputs.archive_page_size, |path| {
+            self.config
+                .site_url(&self.config.page_url_path(Path::new(path)))
+        });
+        let freshness = if force {
+            Freshness::Forced
+        } else {
+            // any dated page may have changed
+            Freshness::Unknown
+        };
+        let mut summary = BuildSummary::default();
+        let start_time = Instant::now();
+        for page in &pages {
+            let frontmatter = Frontmatter {
+                title: page.title.clone(),
+                ..Default::default()
+            };
+            let page_path = self.config.page_url_path(Path::new(&page.path));
+            let canonical_url = self.config.absolute_url(&page_path);
+            let html = self.fill_template(
+                &template,
+                &page.content,
+                &styles,
+                &frontmatter,
+                canonical_url.as_deref(),
+                None,
+            );
+            let html = expand_neighbours(&html, &Default::default()).replace(HEAD_SCRIPTS_SLOT, "");
+            let html = ReadingStats::default().fill(&html);
+            let out_path = out_dir.join(&page.path);
+            self.claim_output(&out_path, Path::new(ARCHIVE_SOURCE))?;
+            self.write_unminified(&out_path, &html).await?;
+            let minified = minify_html(&html)?;
+            self.check_output_size(&out_path, minified.as_bytes())?;
+            // every archive page has its own entry in the build cache
+            let outcome = self
+                .write_output(&out_path, &out_path, minified.as_bytes(), freshness)
+                .await?;
+            summary.record(InputKind::Page, outcome);
+        }
+        summary.pages.time = start_time.elapsed();
+        Ok((summary, style_inputs))
+    }
+
+    /// Finds every markdown file in the source root.
+    ///
+    /// The index, keep and not found inputs are left out since they are always
+    /// rendered on their own, and hidden files/folders are skipped.
+    fn source_pages(&self) -> Result<Vec<PathBuf>> {
+        let inputs = &self.config.inputs;
+        let mut pages = Vec::new();
+        let mut dirs = vec![self.config.roots.source.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let path = entry.path();
+                if self.is_ignored(&path) {
+                    continue;
+                }
+                if entry.file_type()?.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let is_source = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| inputs.extensions.iter().any(|x| x == e));
+                if !is_source {
+                    continue;
+                }
+                let path = path.canonicalize()?;
+                if !self.is_special_input(&path) {
+                    pages.push(path);
+                }
+            }
+        }
+        Ok(pages)
+    }
+
+    /// Pages matching the `always-build` globs.
+    ///
+    /// Like with `build-all`, hidden files and folders are skipped.
+    fn always_built_pages(&self) -> Result<Vec<PathBuf>> {
+        let options = glob::MatchOptions {
+            require_literal_leading_dot: true,
+            ..Default::default()
+        };
+        let root = glob::Pattern::escape(&self.config.roots.source.to_string_lossy());
+        let mut pages = Vec::new();
+        for pattern in &self.config.inputs.always_build {
+            let pattern = pattern.trim_start_matches('/');
+            let invalid = |e: glob::PatternError| {
+                EngineError::Config(format!("invalid always-build pattern {:?}: {}", pattern, e))
+            };
+            // Checked on its own so errors point into the pattern, not the joined path
+            glob::Pattern::new(pattern).map_err(invalid)?;
+            let paths =
+                glob::glob_with(&format!("{}/{}", root, pattern), options).map_err(invalid)?;
+            for path in paths {
+                let path = path.map_err(std::io::Error::from)?;
+                if !path.is_file() {
+                    continue;
+                }
+                let path = path.canonicalize()?;
+                if !self.is_special_input(&path) && !self.is_ignored(&path) {
+                    pages.push(path);
+                }
+            }
+        }
+        Ok(pages)
+    }
+
+    /// Whether a source file or folder, or a folder it is in,
+    /// matches one of the `ignore` patterns.
+    ///
+    /// Patterns with a `/` match the path relative to the source root,
+    /// others match any file or folder name like in a `.gitignore`.
+    fn is_ignored(&self, path: &Path) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let relative = match path.strip_prefix(&self.config.roots.source) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+        relative
+            .ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| {
+                self.ignored.iter().any(|(pattern, anchored)| {
+                    if *anchored {
+                        pattern.matches_path_with(p, options)
+                    } else {
+                        p.file_name()
+                            .is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+                    }
+                })
+            })
+    }
+
+    /// Whether a source file is the index, keep file or 404 page,
+    /// which are rendered as their own inputs.
+    fn is_special_input(&self, path: &Path) -> bool {
+        let inputs = &self.config.inputs;
+        path == inputs.index || path == inputs.keep || path == inputs.not_found
+    }
+
+    /// The other pages in a page's folder, for `list_children`.
+    ///
+    /// Only their frontmatter is read, and pages that aren't rendered
+    /// (or whose frontmatter doesn't parse) are left out.
+    fn child_pages(&self, filename: &Path) -> Result<Vec<(PathBuf, PageInfo)>> {
+        let dir = match filename.parent() {
+            Some(dir) => dir,
+            None => return Ok(Vec::new()),
+        };
+        let extensions = &self.config.inputs.extensions;
+        let mut children = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') || entry.file_type()?.is_dir() {
+                continue;
+            }
+            let path = entry.path().canonicalize()?;
+            let is_source = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| extensions.iter().any(|x| x == e));
+            if !is_source
+                || path == filename
+                || self.is_special_input(&path)
+                || self.is_ignored(&path)
+            {
+                continue;
+            }
+            let markdown = std::fs::read_to_string(&path)?;
+            let raw = match frontmatter::split(&markdown).0 {
+                Some(raw) => raw,
+                None => continue,
+            };
+            let defaults = self.frontmatter_defaults(&path)?;
+            let frontmatter = match Frontmatter::parse_with_defaults(raw, &defaults) {
+                Ok(frontmatter) if frontmatter.render != Some(false) => frontmatter,
+                Ok(_) => continue,
+                Err(e) => {
+                    event!(Level::WARN, r#type = "invalid_child", ?path, %e);
+                    continue;
+                }
+            };
+            let out_path = self
+                .config
+                .page_output_path(path.maybe_unprefix(&self.config.roots.source));
+            let url = self.config.site_url(&self.config.page_url_path(&out_path));
+            children.push((
+                path,
+                PageInfo {
+                    url,
+                    frontmatter,
+                    text: String::new(),
+                },
+            ));
+        }
+        Ok(children)
+    }
+
+    /// Loads the `_defaults.yaml` files that apply to a page, from the source
+    /// root down to the page's folder.
+    fn frontmatter_defaults(&self, filename: &Path) -> Result<Vec<serde_yaml::Value>> {
+        let source = &self.config.roots.source;
+        let relative = match filename.parent().and_then(|p| p.strip_prefix(source).ok()) {
+            Some(relative) => relative,
+            None => return Ok(Vec::new()),
+        };
+        let mut dir = source.clone();
+        let mut defaults = Vec::new();
+        for component in std::iter::once(None).chain(relative.components().map(Some)) {
+            if let Some(component) = component {
+                dir.push(component);
+            }
+            let path = dir.join("_defaults.yaml");
+            let s = match std::fs::read_to_string(&path) {
+                Ok(s) => s,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let value = serde_yaml::from_str(&s).map_err(|e| EngineError::Frontmatter {
+                path: path.clone(),
+                reason: e.to_string(),
+            })?;
+            defaults.push(value);
+        }
+        Ok(defaults)
+    }
+
+    /// Hands an input found while rendering to `render_all`.
+    fn discover(&self, input: RenderingInput, tx: &UnboundedSender<RenderMessage>) {
+        // render_all may have stopped early because of an error
+        let _ = tx.send(RenderMessage::Discovered(input));
     }
 
-    fn spawn_input(
-        self: Arc<Self>,
+    /// Spawns a render of `input` unless it is already being rendered or finished.
+    ///
+    /// Returns whether a render was spawned.
+    fn spawn_if_new(
+        self: &Arc<Self>,
         force: bool,
         input: RenderingInput,
-        tx: UnboundedSender<anyhow::Result<()>>,
-    ) {
-        tokio::spawn(async move {
-            let i2 = input.clone();
-            let r = self.clone().render(input, force, tx.clone()).await;
-            self.render_stack.remove(&i2);
-            self.finished.insert(i2);
-            tx.send(r).unwrap();
-        });
+        tx: &UnboundedSender<RenderMessage>,
+    ) -> bool {
+        // Claimed by inserting it, so two callers can't both spawn it.
+        // A finished render is in `finished` before it leaves `render_stack`,
+        // so checking `finished` after the claim can't miss it.
+        if !self.render_stack.insert(input.clone()) {
+            return false;
+        }
+        if self.finished.contains(&input) {
+            self.render_stack.remove(&input);
+            return false;
+        }
+        if let RenderingInput::Page(ref fname) = input {
+            event!(Level::INFO, r#type = "walk", ?fname);
+        }
+        self.clone().spawn_input(force, input, tx.clone());
+        true
     }
 
-    #[instrument(level = Level::INFO, skip(self))]
-    async fn render_all(self: Arc<Self>, force: bool) -> anyhow::Result<()> {
-        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
-        let stack = {
-            let copy = self.render_stack.clone();
-            self.render_stack.clear();
-            copy
+    /// Renders a markdown string to an HTML fragment.
+    ///
+    /// This runs the same pipeline as pages (frontmatter, syntax highlighting,
+    /// header links and TOC) but does no file I/O, slot substitution or asset
+    /// scheduling. Relative `hyperref:` links are resolved against the source root.
+    pub fn render_markdown_str(&self, input: &str) -> Result<String> {
+        let filename = self.config.roots.source.join("index.md");
+        let mut styles = HashSet::new();
+        let mut new_stack = Vec::new();
+        let rendered = self.render_markdown(&filename, input, &mut styles, &mut new_stack)?;
+        Ok(rendered.html)
+    }
+
+    /// Runs the markdown pipeline over `buf`.
+    ///
+    /// Styles the page needs are added to `styles` and referenced inputs
+    /// (pages, images) are pushed onto `new_stack`.
+    /// Fails if the strict accessibility lint finds a problem.
+    fn render_markdown(
+        &self,
+        filename: &Path,
+        buf: &str,
+        styles: &mut HashSet<&'static str>,
+        new_stack: &mut Vec<RenderingInput>,
+    ) -> Result<RenderedMarkdown> {
+        let defaults = self.frontmatter_defaults(filename)?;
+        let (raw_frontmatter, body) = frontmatter::split(buf);
+        let parser = Parser::new_ext(body, self.config.markdown.options());
+        let mut ctx = ProcessorContext {
+            filename,
+            styles,
+            config: &self.config,
+            new_stack,
+            ss: self.ss,
+            theme: &self.theme,
+            extra_themes: &self.extra_themes,
         };
-        for input in stack {
-            let tx = tx.clone();
-            let this = self.clone();
-            this.spawn_input(force, input, tx);
+        let mut adapter = RenderAdapter::new(parser, &mut ctx);
+
+        let mut s = String::new();
+        html::push_html(&mut s, &mut adapter);
+        s.push_str(&adapter.finish_raw_html());
+        s.push_str(&adapter.render_footnotes());
+
+        s = adapter.setup_image_fallbacks(&s);
+        s = adapter.setup_image_attributes(&s);
+        s = adapter.wrap_tables(&s);
+
+        let toc = adapter.render_toc();
+        s = toc::splice(&s, &toc);
+
+        let missing_alt = adapter.take_missing_alt();
+        if self.config.accessibility_lint == AccessibilityLint::Strict && !missing_alt.is_empty() {
+            return Err(EngineError::Lint {
+                path: filename.to_path_buf(),
+                reason: format!("images without alt text: {}", missing_alt.join(", ")),
+            });
         }
 
-        drop(tx);
+        let mut frontmatter = raw_frontmatter
+            .map(|raw| frontmatter::parse(raw, &defaults))
+            .transpose()
+            .map_err(|e| EngineError::Frontmatter {
+                path: filename.to_path_buf(),
+                reason: e.to_string(),
+            })?;
+        if let Some(frontmatter) = frontmatter.as_mut().filter(|f| f.excerpt.is_none()) {
+            frontmatter.excerpt = adapter.take_excerpt();
+        }
+
+        Ok(RenderedMarkdown {
+            html: s,
+            frontmatter,
+            text: adapter.take_text(),
+            code_themes: adapter.take_code_themes(),
+            broken_links: adapter.take_broken_links(),
+            anchors: adapter.take_anchors(),
+        })
+    }
 
-        while let Some(res) = rx.recv().await {
-            res?;
+    #[instrument(level = Level::INFO, skip(self))]
+    async fn render_all(self: Arc<Self>, force: bool) -> Result<BuildSummary> {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        // number of spawned renders that haven't reported back yet
+        let mut in_flight = 0usize;
+        // take the whole queue at once so nothing queued meanwhile gets lost
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        for input in pending {
+            if self.spawn_if_new(force, input, &tx) {
+                in_flight += 1;
+                self.update_progress(|p| {
+                    p.started += 1;
+                    p.in_flight += 1;
+                });
+            }
         }
 
-        Ok(())
+        // Every render sends what it discovers before it sends its result,
+        // so once nothing is in flight there is no more work left.
+        let mut summary = BuildSummary::default();
+        while in_flight > 0 {
+            // we hold a sender, so the channel can't be closed
+            match rx.recv().await.unwrap() {
+                RenderMessage::Discovered(input) => {
+                    if self.spawn_if_new(force, input, &tx) {
+                        in_flight += 1;
+                        self.update_progress(|p| {
+                            p.started += 1;
+                            p.in_flight += 1;
+                        });
+                    }
+                }
+                RenderMessage::Done(input, elapsed, res) => {
+                    in_flight -= 1;
+                    let kind = input.kind();
+                    let failed = res.is_err();
+                    self.update_progress(|p| {
+                        p.in_flight -= 1;
+                        match kind {
+                            _ if failed => p.failed += 1,
+                            InputKind::Page => p.pages += 1,
+                            InputKind::Image => p.images += 1,
+                            InputKind::Font => p.fonts += 1,
+                            InputKind::Style => p.styles += 1,
+                            InputKind::Script => p.scripts += 1,
+                        }
+                    });
+                    summary.counts_mut(kind).time += elapsed;
+                    match res {
+                        Ok(outcome) => summary.record(kind, outcome),
+                        Err(e) if self.config.build.keep_going => {
+                            summary.record_failure(&input, &e)
+                        }
+                        Err(e) => return Err(e.failed(&input)),
+                    }
+                }
+            }
+        }
+
+        Ok(summary)
     }
 
     #[instrument(level = Level::INFO, skip(self), name = "process_image")]
@@ -136,116 +2166,245 @@ impl Processor {
         self: Arc<Self>,
         input: RenderingInput,
         force: bool,
-    ) -> anyhow::Result<()> {
-        let (inp, out) = match input {
+    ) -> Result<RenderOutcome> {
+        let (hash, format) = match input {
+            RenderingInput::Image {
+                ref output, format, ..
+            } => (output.clone(), format),
+            _ => panic!("expected image enum"),
+        };
+        let r = self.clone().process_image(input, force).await;
+        // pages wait on the size of the WebP version,
+        // so it has to be published even on failure
+        if format == ImageOutput::WebP {
+            self.set_image_size(&hash, r.as_ref().ok().and_then(|(_, size)| *size));
+        }
+        r.map(|(outcome, _)| outcome)
+    }
+
+    /// Converts an image to WebP (or its fallback format), returning its size.
+    async fn process_image(
+        self: Arc<Self>,
+        input: RenderingInput,
+        force: bool,
+    ) -> Result<(RenderOutcome, ImageSize)> {
+        let (inp, out, format) = match input {
             RenderingInput::Image {
                 ref input,
                 ref output,
-            } => (input, output),
+                format,
+            } => (input, output, format),
             _ => panic!("expected image enum"),
         };
-        let out = PathBuf::from(out).with_extension("webp");
+        let out = PathBuf::from(out).with_extension(format.extension());
         let out_path = self.config.roots.output.join("images").join(out);
 
-        if !force && tokio::fs::metadata(&out_path).await.is_ok() {
+        let fresh = if force {
+            false
+        } else if inp.scheme() == "file" {
+            // local images are regenerated when they change
+            match inp.to_file_path() {
+                Ok(source) => freshness(&source, &out_path).await == Freshness::Fresh,
+                Err(_) => false,
+            }
+        } else {
+            tokio::fs::metadata(&out_path).await.is_ok()
+        };
+        if fresh {
             event!(Level::INFO, r#type = "fresh", path = ?out_path);
-            return Ok(());
+            return Ok((
+                RenderOutcome::Fresh,
+                image::image_dimensions(&out_path).ok(),
+            ));
         }
 
         let (mut reader, img_type): (Pin<Box<dyn AsyncRead + Send + Sync>>, ImageFormat) =
             if inp.scheme() == "file" {
-                let path = inp.to_file_path().ok().context("URL to file path")?;
+                let path = inp.to_file_path().map_err(|_| {
+                    EngineError::Config(format!("{} is not a valid file path", inp))
+                })?;
                 let f = File::open(&path).await?;
                 (Box::pin(f), ImageFormat::from_path(&path)?)
+            } else if inp.scheme() == "data" {
+                let (media_type, data) = decode_data_url(inp)?;
+                let img_type = image_format(&media_type).ok_or_else(|| EngineError::Fetch {
+                    url: format!("data:{}", media_type),
+                    reason: format!("unknown content type for image: {}", media_type),
+                })?;
+                (Box::pin(Cursor::new(data)), img_type)
             } else {
                 // fetch the url
-                let r = self
-                    .client
-                    .get(inp.as_str())
-                    .send()
-                    .await
-                    .map_err(|_| anyhow::anyhow!("fetch failed"))?;
-                let content_type = &r.header("Content-Type").context("Get image content type")?[0];
-                let img_type = match content_type.as_str() {
-                    "image/webp" => ImageFormat::WebP,
-                    "image/png" => ImageFormat::Png,
-                    "image/jpeg" => ImageFormat::Jpeg,
-                    "image/gif" => ImageFormat::Gif,
-                    _ => {
-                        return Err(anyhow::anyhow!(
-                            "Unknown content type for image: {}",
-                            content_type
-                        ))
-                    }
-                };
-                (Box::pin(r.compat()), img_type)
+                let r = self.fetch(inp.as_str()).await?;
+                let content_type = r.content_type.ok_or_else(|| EngineError::Fetch {
+                    url: inp.to_string(),
+                    reason: "no content type".to_string(),
+                })?;
+                let img_type = image_format(&content_type).ok_or_else(|| EngineError::Fetch {
+                    url: inp.to_string(),
+                    reason: format!("unknown content type for image: {}", content_type),
+                })?;
+                (Box::pin(Cursor::new(r.body)), img_type)
             };
 
-        use std::time::Instant;
         let start_time = Instant::now();
 
-        match img_type {
-            ImageFormat::WebP => {
+        if let Some(parent) = out_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let size = match img_type {
+            img_type if img_type == format.image_format() => {
                 // Directly copy to the file.
                 let mut f = File::create(&out_path).await?;
                 tokio::io::copy(&mut reader, &mut f).await?;
+                f.flush().await?;
+                image::image_dimensions(&out_path).ok()
             }
             img_type => {
-                // Convert to WebP, then write to file.
+                // Convert, then write to file.
                 let mut v = Vec::new();
                 reader.read_to_end(&mut v).await?;
                 let cursor = Cursor::new(&v);
                 let mut img_in = image::io::Reader::new(cursor);
                 img_in.set_format(img_type);
-                if let Some(parent) = out_path.parent() {
-                    tokio::fs::create_dir_all(parent).await?;
-                }
                 let mut f = File::create(&out_path).await?;
-                let decoded = img_in.decode()?;
-                // WebP encoding has to be done on a separate thread since it is !Send
-                let (tx2, mut rx2) = tokio::sync::mpsc::unbounded_channel();
-                std::thread::spawn(move || {
-                    let encoder = webp::Encoder::from_image(&decoded);
-                    let mem = encoder.encode(75.);
-                    tx2.send(mem.to_vec()).unwrap();
-                });
-                let res = rx2.recv().await.unwrap();
+                let permit = self.encode_permits.acquire().await.unwrap();
+                let decoded = img_in.decode().map_err(|e| {
+                    if inp.scheme() == "file" {
+                        EngineError::Image(e)
+                    } else {
+                        EngineError::Fetch {
+                            url: inp.to_string(),
+                            reason: format!("decoding the image failed: {}", e),
+                        }
+                    }
+                })?;
+                let size = decoded.dimensions();
+                let res = match format {
+                    ImageOutput::WebP => {
+                        // WebP encoding has to be done on a separate thread since it is !Send
+                        let (tx2, mut rx2) = tokio::sync::mpsc::unbounded_channel();
+                        std::thread::spawn(move || {
+                            let encoder = webp::Encoder::from_image(&decoded);
+                            let mem = encoder.encode(75.);
+                            tx2.send(mem.to_vec()).unwrap();
+                        });
+                        rx2.recv().await.unwrap()
+                    }
+                    ImageOutput::Png => {
+                        let mut encoded = Vec::new();
+                        decoded.write_to(&mut encoded, ImageOutputFormat::Png)?;
+                        encoded
+                    }
+                    ImageOutput::Jpeg => {
+                        // JPEG has no alpha channel
+                        let decoded = image::DynamicImage::ImageRgb8(decoded.to_rgb8());
+                        let mut encoded = Vec::new();
+                        decoded.write_to(&mut encoded, ImageOutputFormat::Jpeg(85))?;
+                        encoded
+                    }
+                };
+                drop(permit);
                 f.write_all(&res).await?;
                 event!(
                     Level::INFO,
                     r#type = "webp_process",
+                    ?format,
                     initial_len = v.len(),
                     new_len = res.len(),
                     change = %((res.len() as f64) - (v.len() as f64)) / (v.len() as f64) * 100.
                 );
+                Some(size)
             }
-        }
+        };
 
         let end_time = Instant::now();
         event!(Level::INFO, r#type = "image_process", path = ?out_path, time = %(end_time - start_time).as_secs_f64());
 
-        Ok(())
+        Ok((RenderOutcome::Written, size))
+    }
+
+    /// Downloads a remote asset.
+    ///
+    /// Connection errors, timeouts and server errors are retried
+    /// with exponential backoff, other failures are not.
+    async fn fetch(&self, url: &str) -> Result<Fetched> {
+        let retries = self.config.build.fetch_retries;
+        let mut backoff = FETCH_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            // not held while backing off, so other downloads can go ahead
+            let permit = self.fetch_permits.acquire().await.unwrap();
+            let r = self.fetch_once(url).await;
+            drop(permit);
+            match r {
+                Ok(fetched) => return Ok(fetched),
+                Err((true, reason)) if attempt < retries => {
+                    attempt += 1;
+                    event!(Level::WARN, r#type = "fetch_retry", %url, %reason, attempt, ?backoff);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err((_, reason)) => {
+                    let reason = match attempt {
+                        0 => reason,
+                        1 => format!("{} (after 1 retry)", reason),
+                        n => format!("{} (after {} retries)", reason, n),
+                    };
+                    return Err(EngineError::Fetch {
+                        url: url.to_string(),
+                        reason,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Makes a single attempt at downloading a remote asset,
+    /// giving up after the configured timeout.
+    ///
+    /// Errors say whether they may go away when retried.
+    async fn fetch_once(&self, url: &str) -> Result<Fetched, (bool, String)> {
+        let timeout = self.config.build.fetch_timeout;
+        let request = async {
+            let mut r = self
+                .client
+                .get(url)
+                .send()
+                .await
+                .map_err(|e| (true, format!("request failed: {}", e)))?;
+            let status = r.status();
+            // an error page isn't the asset, but a server error may go away
+            if !status.is_success() {
+                return Err((
+                    status.is_server_error(),
+                    format!("{} {}", status, status.canonical_reason()),
+                ));
+            }
+            let body = r
+                .body_bytes()
+                .await
+                .map_err(|e| (true, format!("reading the response failed: {}", e)))?;
+            let content_type = r.header("Content-Type").map(|v| v[0].to_string());
+            Ok(Fetched { content_type, body })
+        };
+        tokio::time::timeout(Duration::from_secs(timeout), request)
+            .await
+            .map_err(|_| (true, format!("timed out after {}s", timeout)))?
     }
 
     async fn _style_regex_replacer(
         self: Arc<Self>,
         capture: &Captures<'_>,
-        force: bool,
-        tx: UnboundedSender<anyhow::Result<()>>,
-    ) -> anyhow::Result<String> {
+        tx: UnboundedSender<RenderMessage>,
+    ) -> Result<String> {
         let url = capture.name("url").unwrap();
         // Fetch URL
         let contents = {
-            let mut s = String::new();
-            let mut r = self
-                .client
-                .get(url.as_str())
-                .send()
-                .await
-                .map_err(|_| anyhow::anyhow!("fetch failed"))?
-                .compat();
-            r.read_to_string(&mut s).await?;
-            s
+            let r = self.fetch(url.as_str()).await?;
+            String::from_utf8(r.body).map_err(|e| EngineError::Fetch {
+                url: url.as_str().to_string(),
+                reason: format!("stylesheet is not valid UTF-8: {}", e),
+            })?
         };
         // Match font URLs inside...
         let re2 = Regex::new(r"url\((?P<url>\S+)\)").unwrap();
@@ -260,7 +2419,7 @@ impl Processor {
                 parsed
                     .path_segments()
                     .unwrap()
-                    .last()
+                    .next_back()
                     .unwrap()
                     .split(".")
                     .last()
@@ -274,11 +2433,11 @@ impl Processor {
                 RenderingInput::Font { ref output, .. } => output,
                 _ => unreachable!(),
             };
-            let new_url = format!("url(/fonts/{})", output_filename);
-            if !self.render_stack.contains(&input) && !self.finished.contains(&input) {
-                self.render_stack.insert(input.clone());
-                self.clone().spawn_input(force, input, tx.clone());
-            }
+            let new_url = format!(
+                "url({})",
+                self.config.site_url(&format!("fonts/{}", output_filename))
+            );
+            self.discover(input, &tx);
             new_url
         });
         Ok(contents.to_string())
@@ -289,41 +2448,105 @@ impl Processor {
         self: Arc<Self>,
         input: RenderingInput,
         force: bool,
-        tx: UnboundedSender<anyhow::Result<()>>,
-    ) -> anyhow::Result<()> {
+        tx: UnboundedSender<RenderMessage>,
+    ) -> Result<RenderOutcome> {
         let sname = match input {
-            RenderingInput::Style(sname) => sname,
+            RenderingInput::Style(ref sname) => sname,
             _ => panic!("Expected style input"),
         };
-        let path = self
-            .config
-            .lib
-            .styles
-            .chunks_root
-            .join(sname)
-            .with_extension("css");
-        let out_path = self
-            .config
-            .roots
-            .output
-            .join("css")
-            .join(sname)
-            .with_extension("css");
+        let (path, out_path) = match self.style_paths(sname) {
+            Some(paths) => paths,
+            None => {
+                event!(Level::WARN, r#type = "invalid_style", %sname);
+                return Ok(RenderOutcome::Skipped);
+            }
+        };
 
         if !path.exists() {
             event!(Level::INFO, r#type = "nonexistent_source", ?path);
-            return Ok(());
+            return Ok(RenderOutcome::Skipped);
         }
 
-        let out_path_metadata = tokio::fs::metadata(&out_path).await;
-        if !force
-            && out_path_metadata.is_ok()
-            && out_path_metadata?.modified()? > tokio::fs::metadata(&path).await?.modified()?
-        {
+        let freshness = if force {
+            Freshness::Forced
+        } else {
+            freshness(&path, &out_path).await
+        };
+        if freshness == Freshness::Fresh {
             event!(Level::INFO, r#type = "fresh", path = ?out_path);
-            return Ok(());
+            return Ok(RenderOutcome::Fresh);
+        }
+
+        let minified_css = self.clone().process_style_chunk(&path, tx).await?;
+        self.check_output_size(&out_path, minified_css.as_bytes())?;
+        self.write_output(&path, &out_path, minified_css.as_bytes(), freshness)
+            .await
+    }
+
+    #[instrument(level = Level::INFO, skip(self), name = "process_style_bundle")]
+    async fn render_style_bundle(
+        self: Arc<Self>,
+        input: RenderingInput,
+        force: bool,
+        tx: UnboundedSender<RenderMessage>,
+    ) -> Result<RenderOutcome> {
+        let names = match input {
+            RenderingInput::StyleBundle(ref names) => names,
+            _ => panic!("Expected style bundle input"),
+        };
+        let out_path = self.style_bundle_path(names);
+        // style_links only bundles valid names
+        let paths = names
+            .iter()
+            .filter_map(|sname| self.style_paths(sname))
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+
+        let freshness = if force {
+            Freshness::Forced
+        } else {
+            let mut all_fresh = true;
+            for path in &paths {
+                all_fresh &= freshness(path, &out_path).await == Freshness::Fresh;
+            }
+            if all_fresh {
+                event!(Level::INFO, r#type = "fresh", path = ?out_path);
+                return Ok(RenderOutcome::Fresh);
+            }
+            Freshness::Unknown
+        };
+
+        let mut bundle = String::new();
+        for path in &paths {
+            bundle.push_str(&self.clone().process_style_chunk(path, tx.clone()).await?);
         }
+        self.check_output_size(&out_path, bundle.as_bytes())?;
+        // the bundle has no single source, so it is cached by its own path
+        self.write_output(&out_path, &out_path, bundle.as_bytes(), freshness)
+            .await
+    }
+
+    /// Like `process_style_chunk`, but only processes each chunk once per build.
+    async fn style_chunk_contents(
+        self: Arc<Self>,
+        path: &Path,
+        tx: UnboundedSender<RenderMessage>,
+    ) -> Result<String> {
+        if let Some(css) = self.processed_styles.get(path) {
+            return Ok(css.clone());
+        }
+        let css = self.clone().process_style_chunk(path, tx).await?;
+        self.processed_styles
+            .insert(path.to_path_buf(), css.clone());
+        Ok(css)
+    }
 
+    /// Reads a style chunk, downloading the fonts it declares, and minifies it.
+    async fn process_style_chunk(
+        self: Arc<Self>,
+        path: &Path,
+        tx: UnboundedSender<RenderMessage>,
+    ) -> Result<String> {
         // Read file and check for special decls
         let buf = {
             let mut s = String::new();
@@ -331,7 +2554,7 @@ impl Processor {
             f.read_to_string(&mut s).await?;
             s
         };
-        let re = Regex::new(r"/\*\*.*@font (?P<url>\S+).*\*/")?;
+        let re = Regex::new(r"/\*\*.*@font (?P<url>\S+).*\*/").unwrap();
 
         // src/regex/re_unicode.rs:569-588, regex crate
         // The slower path, which we use if the replacement needs access to
@@ -341,8 +2564,8 @@ impl Processor {
             let text = &buf;
             let limit = 0;
             let it = re.captures_iter(text).enumerate().collect::<Vec<_>>();
-            if it.len() <= 0 {
-                Ok::<_, anyhow::Error>(Cow::Borrowed(text))
+            if it.is_empty() {
+                Ok::<_, EngineError>(Cow::Borrowed(text))
             } else {
                 let mut new = String::with_capacity(text.len());
                 let mut last_match = 0;
@@ -357,7 +2580,7 @@ impl Processor {
                     // TODO: Work a little harder to figure out a way to parallelize this
                     new.push_str(
                         self.clone()
-                            ._style_regex_replacer(&cap, force, tx.clone())
+                            ._style_regex_replacer(&cap, tx.clone())
                             .await?
                             .as_ref(),
                     );
@@ -368,28 +2591,16 @@ impl Processor {
             }
         }?;
 
-        if let Some(p) = out_path.parent() {
-            tokio::fs::create_dir_all(p).await?;
-        }
-        // Minify style first
-        let minified_css = {
-            let minified =
-                html_minifier::css::minify(&buf).map_err(|_| anyhow::anyhow!("minify failed"))?;
-            event!(
-                Level::INFO,
-                r#type = "minified",
-                in_len = buf.len(),
-                new_len = minified.len(),
-                change = %(((minified.len() as f64) - (buf.len() as f64)) / buf.len() as f64) * 100.
-            );
-            Ok::<_, anyhow::Error>(minified)
-        }?;
-        let mut f = File::create(&out_path).await?;
-        f.write_all(minified_css.as_bytes()).await?;
-
-        event!(Level::INFO, r#type = "new", path = ?out_path);
-
-        Ok(())
+        let minified =
+            html_minifier::css::minify(&buf).map_err(|e| EngineError::Minify(e.to_string()))?;
+        event!(
+            Level::INFO,
+            r#type = "minified",
+            in_len = buf.len(),
+            new_len = minified.len(),
+            change = %(((minified.len() as f64) - (buf.len() as f64)) / buf.len() as f64) * 100.
+        );
+        Ok(minified)
     }
 
     #[instrument(level = Level::INFO, skip(self), name = "process_font")]
@@ -397,7 +2608,7 @@ impl Processor {
         self: Arc<Self>,
         input: RenderingInput,
         force: bool,
-    ) -> anyhow::Result<()> {
+    ) -> Result<RenderOutcome> {
         // Just download the file to the given path
         let (url, output) = match input {
             RenderingInput::Font {
@@ -410,25 +2621,103 @@ impl Processor {
 
         if !force && tokio::fs::metadata(&out_path).await.is_ok() {
             event!(Level::INFO, r#type = "fresh", %url);
-            return Ok(());
+            return Ok(RenderOutcome::Fresh);
         }
 
-        let mut r = self
-            .client
-            .get(url.as_str())
-            .send()
-            .await
-            .map_err(|_| anyhow::anyhow!("fetch failed"))?
-            .compat();
+        let r = self.fetch(url.as_str()).await?;
         if let Some(parent) = out_path.parent() {
             tokio::fs::create_dir_all(parent).await?;
         }
-        let mut f = File::create(&out_path).await?;
-        tokio::io::copy(&mut r, &mut f).await?;
+        tokio::fs::write(&out_path, &r.body).await?;
 
         event!(Level::INFO, r#type = "new", path = ?out_path);
 
-        Ok(())
+        Ok(RenderOutcome::Written)
+    }
+
+    #[instrument(level = Level::INFO, skip(self), name = "process_script")]
+    async fn render_script(
+        self: Arc<Self>,
+        input: RenderingInput,
+        force: bool,
+    ) -> Result<RenderOutcome> {
+        let path = match input {
+            RenderingInput::Script(ref path) => path,
+            _ => panic!("Expected script input"),
+        };
+        let out_path = self.script_output_path(path);
+        let freshness = if force {
+            Freshness::Forced
+        } else {
+            freshness(path, &out_path).await
+        };
+        if freshness == Freshness::Fresh {
+            event!(Level::INFO, r#type = "fresh", path = ?out_path);
+            return Ok(RenderOutcome::Fresh);
+        }
+        let contents = tokio::fs::read(path).await?;
+        self.write_output(path, &out_path, &contents, freshness)
+            .await
+    }
+
+    /// Output path of a local script, named by a hash of its source path.
+    fn script_output_path(&self, source: &Path) -> PathBuf {
+        use sha2::Digest;
+        let hash = format!(
+            "{:x}",
+            sha2::Sha256::digest(source.to_string_lossy().as_bytes())
+        );
+        let name = source.file_name().unwrap_or_default().to_string_lossy();
+        self.config
+            .roots
+            .output
+            .join("js")
+            .join(format!("{}-{}", &hash[..16], name))
+    }
+
+    /// Script tags for a page's `scripts`, along with the local scripts
+    /// they need.
+    ///
+    /// URLs are used as is, and local paths are resolved like images:
+    /// from the source root if they start with `/`, otherwise from the page's
+    /// folder or else the assets root.
+    fn script_tags(&self, page: &Path, scripts: &[String]) -> (String, Vec<RenderingInput>) {
+        let roots = &self.config.roots;
+        let mut tags = String::new();
+        let mut inputs = Vec::new();
+        for script in scripts {
+            let src = if Url::parse(script).is_ok() || script.starts_with("//") {
+                script.clone()
+            } else {
+                let candidates = match script.strip_prefix('/') {
+                    Some(path) => vec![roots.source.join(path)],
+                    None => vec![
+                        page.parent().unwrap_or(&roots.source).join(script),
+                        roots.assets.join(script),
+                    ],
+                };
+                let found = candidates
+                    .into_iter()
+                    .find(|p| p.is_file())
+                    .and_then(|p| p.canonicalize().ok());
+                let source = match found {
+                    Some(source) => source,
+                    None => {
+                        event!(Level::WARN, r#type = "missing_script", ?page, %script);
+                        continue;
+                    }
+                };
+                let out_path = self.script_output_path(&source);
+                inputs.push(RenderingInput::Script(source));
+                self.config
+                    .site_url(&to_url_path(out_path.maybe_unprefix(&roots.output)))
+            };
+            tags.push_str(&format!(
+                r#"<script src="{}" defer></script>"#,
+                src.replace('"', "&quot;")
+            ));
+        }
+        (tags, inputs)
     }
 
     #[instrument(level = Level::INFO, skip(self))]
@@ -436,24 +2725,28 @@ impl Processor {
         self: Arc<Self>,
         input: RenderingInput,
         force: bool,
-        tx: UnboundedSender<anyhow::Result<()>>,
-    ) -> anyhow::Result<()> {
+        tx: UnboundedSender<RenderMessage>,
+    ) -> Result<RenderOutcome> {
         let out_dir = &self.config.roots.output;
         let base_dir = &self.config.roots.source;
-        let style_chunks_root = &self.config.lib.styles.chunks_root;
         let prelude_html = &self.config.lib.prelude_location;
         let filename = match input {
             RenderingInput::Index => &self.config.inputs.index,
             RenderingInput::Keep => &self.config.inputs.keep,
+            RenderingInput::NotFound => &self.config.inputs.not_found,
             RenderingInput::Style(..) => return self.render_style(input, force, tx).await,
+            RenderingInput::StyleBundle(..) => {
+                return self.render_style_bundle(input, force, tx).await
+            }
             RenderingInput::Font { .. } => return self.render_font(input, force).await,
+            RenderingInput::Script(..) => return self.render_script(input, force).await,
             RenderingInput::Image { .. } => return self.render_image(input, force).await,
             RenderingInput::Page(ref o) => o,
         };
 
         if !filename.exists() {
             event!(Level::INFO, r#type = "nonexistent_source", path = ?filename);
-            return Ok(());
+            return Ok(RenderOutcome::Skipped);
         }
 
         // create out dir if doesn't exist
@@ -462,9 +2755,20 @@ impl Processor {
         }
 
         // NOTE: can't canonicalize here since the output path may not exist
-        let out_path = out_dir
-            .join(filename.strip_prefix(&base_dir)?)
-            .with_extension("html");
+        let out_path = if input == RenderingInput::NotFound {
+            // Served by the host for missing paths, so the name is fixed
+            out_dir.join("404.html")
+        } else {
+            out_dir.join(
+                self.config
+                    .page_output_path(filename.strip_prefix(base_dir).map_err(|_| {
+                        EngineError::Config(format!(
+                            "{:?} is not inside the source root {:?}",
+                            filename, base_dir
+                        ))
+                    })?),
+            )
+        };
 
         let buf = {
             let mut s = String::new();
@@ -473,166 +2777,329 @@ impl Processor {
             Ok::<_, std::io::Error>(s)
         }?;
 
-        let mut styles = {
-            let mut h = HashSet::new();
-            h.insert("_global");
-            h
-        };
-
-        let (html, frontmatter) = {
-            /* No awaits from here... */
+        let mut styles = HashSet::new();
 
-            let parser = Parser::new_ext(&buf, Options::all());
+        let (html, frontmatter, text, code_themes) = {
             let mut new_stack = Vec::new();
-            let mut ctx = ProcessorContext {
-                filename,
-                styles: &mut styles,
-                config: &self.config,
-                finished: &self.finished,
-                render_stack: &self.render_stack,
-                new_stack: &mut new_stack,
-                ss: &self.ss,
-                theme: &self.ts.themes[&self.config.theme],
-            };
-            let mut adapter = RenderAdapter::new(parser, &mut ctx);
+            let rendered = self.render_markdown(filename, &buf, &mut styles, &mut new_stack)?;
 
-            let mut s = String::new();
-            html::push_html(&mut s, &mut adapter);
+            let mut images = HashSet::new();
+            for input in new_stack {
+                if let RenderingInput::Image { ref output, .. } = input {
+                    images.insert(output.clone());
+                }
+                self.discover(input, &tx);
+            }
 
-            s = adapter.postprocess_syntax_highlighting(&s);
-            s = adapter.setup_header_links(&s);
+            // Give images their size to prevent layout shift
+            let mut html = rendered.html;
+            for hash in images {
+                if let Some((width, height)) = self.image_size(&hash).await {
+                    // a fallback has the same size as the WebP version
+                    for format in ImageOutput::ALL {
+                        let src = format!(
+                            r#"src="{}""#,
+                            self.config.site_url(&format!(
+                                "images/{}.{}",
+                                hash,
+                                format.extension()
+                            ))
+                        );
+                        html = html.replace(
+                            &src,
+                            &format!(r#"{} width="{}" height="{}""#, src, width, height),
+                        );
+                    }
+                }
+            }
 
-            let toc = adapter.render_toc();
-            s = format!("{}{}", toc, s);
+            (
+                html,
+                rendered.frontmatter,
+                rendered.text,
+                rendered.code_themes,
+            )
+        };
+        let mut frontmatter = frontmatter.ok_or_else(|| EngineError::Frontmatter {
+            path: filename.clone(),
+            reason: "no frontmatter found".to_string(),
+        })?;
 
-            let fm = adapter.frontmatter.take();
-            /* ...to here. */
+        // Unpublished pages are only walked for what they link to
+        if input == RenderingInput::Keep || frontmatter.render == Some(false) {
+            event!(Level::INFO, r#type = "unpublished", path = ?filename);
+            return Ok(RenderOutcome::Skipped);
+        }
 
-            for input in new_stack {
-                self.clone().spawn_input(force, input, tx.clone());
+        // A layout can make the page something other than HTML
+        let (template_path, extension) = match frontmatter.layout.as_deref() {
+            Some(layout) => {
+                let path = self
+                    .layout_path(layout)
+                    .ok_or_else(|| EngineError::Frontmatter {
+                        path: filename.clone(),
+                        reason: format!("invalid layout {:?}", layout),
+                    })?;
+                let extension = path
+                    .extension()
+                    .map_or("html".to_string(), |e| e.to_string_lossy().to_lowercase());
+                (path, extension)
             }
-
-            (s, fm)
+            None => (prelude_html.clone(), "html".to_string()),
+        };
+        let is_html = extension == "html" || extension == "htm";
+        let out_path = if is_html || input == RenderingInput::NotFound {
+            out_path
+        } else {
+            // not a page of its own, so never in a folder for pretty URLs
+            out_dir.join(filename.maybe_unprefix(base_dir).with_extension(&extension))
         };
-        let frontmatter = frontmatter.context("No frontmatter found!")?;
+        self.claim_output(&out_path, filename)?;
 
-        let styles = {
-            let mut new_styles = Vec::new();
-            for sname in styles.into_iter() {
-                let path = style_chunks_root.join(sname).with_extension("css");
-                // skip missing files
-                if let Ok(_) = AsRef::<Path>::as_ref(&path).canonicalize() {
-                    let css_out_path = out_dir.join("css").join(sname).with_extension("css");
-                    let input = RenderingInput::Style(sname);
-                    if !self.render_stack.contains(&input) && !self.finished.contains(&input) {
-                        self.render_stack.insert(input.clone());
-                        self.clone().spawn_input(force, input, tx.clone());
-                    }
-                    new_styles.push(format!(
-                        r#"
-    <link rel="preload" href="/{0}" as="style" />
-    <link rel="stylesheet" type="text/css" href="/{0}" />
-    "#,
-                        css_out_path
-                            .strip_prefix(out_dir)
-                            .unwrap_or(&css_out_path)
-                            .to_str()
-                            .unwrap_or("unknown")
-                            .replace("\\", "/")
-                    ));
-                }
+        // Counted before the text is kept for the search index
+        let stats = ReadingStats::of(&text);
+        if frontmatter.time_to_read.is_none() {
+            let minutes = stats.minutes(self.config.reading_wpm);
+            frontmatter.time_to_read =
+                Some(self.config.ttr_format.replace("{}", &minutes.to_string()));
+        }
+
+        // Path of the page relative to the output root
+        let page_path = self.config.page_url_path(out_path.maybe_unprefix(out_dir));
+        if let RenderingInput::Index | RenderingInput::Page(..) = input {
+            self.pages.insert(
+                filename.clone(),
+                PageInfo {
+                    url: self.config.site_url(&page_path),
+                    frontmatter: frontmatter.clone(),
+                    text,
+                },
+            );
+        }
+
+        // Social card metadata
+        let canonical_url = frontmatter
+            .canonical
+            .clone()
+            .or_else(|| self.config.absolute_url(&page_path));
+        let og_image = match frontmatter.cover.as_deref().map(Url::parse) {
+            Some(Ok(parsed)) => {
+                let (input, path) = RenderingInput::image(parsed);
+                self.discover(input, &tx);
+                Some(
+                    self.config
+                        .absolute_url(&path)
+                        .unwrap_or_else(|| self.config.site_url(&path)),
+                )
             }
-            Ok::<_, std::io::Error>(new_styles)
-        }?;
-        let html = {
-            let mut f = File::open(prelude_html).await?;
-            let mut s = String::new();
-            f.read_to_string(&mut s).await?;
-            Ok::<_, std::io::Error>(s)
-        }?
-        .replace("@@@SLOT_STYLES@@@", &format!("\n{}\n", styles.join("\n")))
-        .replace("@@@SLOT_CONTENT@@@", &html)
-        .replace("@@@SLOT_TITLE@@@", &frontmatter.title);
-
-        let html = {
-            let mut html = html;
-
-            let date_r = RegexBuilder::new(r#"<!-- @@@IF_DATE@@@ -->(.*?)<!-- @@@ENDIF@@@ -->"#)
-                .dot_matches_new_line(true)
-                .build()
-                .unwrap();
-            if let Some(d) = frontmatter.date {
-                html = date_r
-                    .replace_all(&html, |caps: &Captures| {
-                        // Expand dates inside and all that
-                        let inner = &caps[1];
-                        let date = d.format(DATE_FORMAT).to_string();
-                        inner.replace("@@@SLOT_DATE@@@", &date)
-                    })
-                    .to_string();
-            } else {
-                html = date_r.replace_all(&html, "").to_string();
-            }
-            let ttr_r =
-                RegexBuilder::new(r#"<!-- @@@IF_TIME_TO_READ@@@ -->(.*?)<!-- @@@ENDIF@@@ -->"#)
-                    .dot_matches_new_line(true)
-                    .build()
-                    .unwrap();
-            if let Some(ttr) = frontmatter.time_to_read {
-                html = ttr_r
-                    .replace_all(&html, |caps: &Captures| {
-                        // Expand ttr inside and all that
-                        let inner = &caps[1];
-                        inner.replace("@@@SLOT_TIME_TO_READ@@@", &ttr)
-                    })
-                    .to_string();
-            } else {
-                html = ttr_r.replace_all(&html, "").to_string();
+            Some(Err(e)) => {
+                event!(Level::WARN, r#type = "invalid_cover", cover = ?frontmatter.cover, %e);
+                None
             }
+            None => None,
+        };
 
+        let prelude = self.read_template(&template_path).await?;
+        let has_post_list = html.contains(POST_LIST_SLOT) || prelude.contains(POST_LIST_SLOT);
+
+        // Chunks every page gets and the ones it asks for on top of the ones it needs
+        let mut styles: HashSet<&str> = styles;
+        styles.extend(self.always_styles());
+        styles.extend(frontmatter.styles.iter().flatten().map(String::as_str));
+        if has_post_list {
+            styles.insert("post-list");
+        }
+        let purge_css = self.config.outputs.purge_css;
+        let chunks = if purge_css {
+            self.style_chunks(&styles)
+        } else {
+            Vec::new()
+        };
+        let (mut styles, style_inputs) = if purge_css {
+            // filled in once the page is known
+            (PURGED_STYLES.to_string(), Vec::new())
+        } else {
+            self.style_links(&styles)
+        };
+        for theme in &code_themes {
+            let out_path = self.write_syntax_style(theme, force).await?;
+            styles.push_str(&self.style_link(&out_path));
+        }
+        for input in style_inputs {
+            self.discover(input, &tx);
+        }
+        let scripts = frontmatter.scripts.as_deref().unwrap_or_default();
+        let (scripts, script_inputs) = self.script_tags(filename, scripts);
+        for input in script_inputs {
+            self.discover(input, &tx);
+        }
+        let html = self
+            .fill_template(
+                &prelude,
+                &html,
+                &styles,
+                &frontmatter,
+                canonical_url.as_deref(),
+                og_image.as_deref(),
+            )
+            .replace(HEAD_SCRIPTS_SLOT, &scripts);
+        let html = stats.fill(&html);
+        let list_children = frontmatter.list_children == Some(true);
+        let children = if list_children {
+            let mut children = Vec::new();
+            for (path, child) in self.child_pages(filename)? {
+                self.discover(RenderingInput::Page(path), &tx);
+                children.push(child);
+            }
+            listing::children_list(children)
+        } else {
+            String::new()
+        };
+        // the slot is usually a paragraph of its own in the page
+        let html = html
+            .replace(&format!("<p>{}</p>", CHILDREN_SLOT), &children)
+            .replace(CHILDREN_SLOT, &children);
+        let html = if purge_css {
+            self.clone()
+                .write_purged_styles(html, &chunks, force, &tx)
+                .await?
+        } else {
             html
         };
 
-        // Minify HTML
-        let minified = html_minifier::minify(&html)?;
+        // only dated pages have neighbours, so the others can be written now
+        let has_neighbours = frontmatter.date.is_some() && has_neighbour_blocks(&html);
+        let html = match input {
+            RenderingInput::Index | RenderingInput::Page(..) if has_post_list || has_neighbours => {
+                self.deferred.lock().unwrap().push(DeferredPage {
+                    source: filename.clone(),
+                    out_path,
+                    html,
+                    is_html,
+                });
+                return Ok(RenderOutcome::Deferred);
+            }
+            _ => expand_neighbours(&html, &Default::default()),
+        };
 
-        event!(
-            Level::INFO,
-            r#type = "minified",
-            in_len = html.len(),
-            new_len = minified.len(),
-            change = %(((minified.len() as f64) - (html.len() as f64)) / html.len() as f64) * 100.
-        );
+        let minified = if is_html {
+            self.write_unminified(&out_path, &html).await?;
+            minify_html(&html)?
+        } else {
+            html
+        };
+        self.check_output_size(&out_path, minified.as_bytes())?;
 
-        // write only if file doesn't exist
-        let needs_update = if let (Ok(out_metadata), Ok(in_metadata)) = (
-            tokio::fs::metadata(&out_path).await,
-            tokio::fs::metadata(&filename).await,
-        ) {
-            in_metadata.modified()? >= out_metadata.modified()?
+        let freshness = if force {
+            Freshness::Forced
+        } else if list_children {
+            // the other pages may have changed even if this one didn't
+            Freshness::Unknown
         } else {
-            // failed to get metadata, or either path doesn't exist
-            true
+            freshness(filename, &out_path).await
         };
-        if !needs_update && !force {
+        if freshness == Freshness::Fresh {
             // nothing to do
             event!(Level::INFO, r#type = "fresh", path = ?out_path);
+            Ok(RenderOutcome::Fresh)
         } else {
-            // first, recursively create parents
-            if let Some(p) = out_path.parent() {
-                tokio::fs::create_dir_all(p).await?;
-            }
-
-            if input == RenderingInput::Keep {
-                event!(Level::INFO, r#type = "special_keep", path = ?out_path);
-            } else {
-                let mut f = File::create(&out_path).await?;
-                f.write_all(minified.as_bytes()).await?;
-                // println!("{}", html);
-                event!(Level::INFO, r#type = "new", path = ?out_path);
-            }
+            self.write_output(filename, &out_path, minified.as_bytes(), freshness)
+                .await
         }
+    }
+}
 
-        Ok(())
+/// Whether an output is up to date with its source
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Freshness {
+    /// The output is newer than the source
+    Fresh,
+    /// Both have the same modification time, which happens with coarse
+    /// timestamps when the source is edited right after a build
+    Unknown,
+    /// The output is older than the source or missing
+    Stale,
+    /// A rebuild was forced
+    Forced,
+}
+
+/// Compares modification times of a source and its output.
+async fn freshness(source: &Path, output: &Path) -> Freshness {
+    let modified = |m: std::io::Result<std::fs::Metadata>| m.and_then(|m| m.modified()).ok();
+    match (
+        modified(tokio::fs::metadata(output).await),
+        modified(tokio::fs::metadata(source).await),
+    ) {
+        (Some(out), Some(inp)) if out > inp => Freshness::Fresh,
+        (Some(out), Some(inp)) if out == inp => Freshness::Unknown,
+        // failed to get metadata, or either path doesn't exist
+        _ => Freshness::Stale,
     }
 }
+
+/// Minifies a generated HTML page
+fn minify_html(html: &str) -> Result<String> {
+    let minified = html_minifier::minify(html).map_err(|e| EngineError::Minify(e.to_string()))?;
+
+    event!(
+        Level::INFO,
+        r#type = "minified",
+        in_len = html.len(),
+        new_len = minified.len(),
+        change = %(((minified.len() as f64) - (html.len() as f64)) / html.len() as f64) * 100.
+    );
+
+    Ok(minified)
+}
+
+/// The ids of the elements of an HTML document.
+fn element_ids(html: &str) -> HashSet<String> {
+    let r = Regex::new(r#"\sid\s*=\s*"([^"]*)""#).unwrap();
+    r.captures_iter(html).map(|c| c[1].to_string()).collect()
+}
+
+/// A hidden folder next to `path`, e.g. `.out.staging` for `out`.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+/// Whether a filled template links to the previous or next page.
+fn has_neighbour_blocks(html: &str) -> bool {
+    html.contains("@@@IF_PREV_URL@@@") || html.contains("@@@IF_NEXT_URL@@@")
+}
+
+/// Expands the `PREV_URL` and `NEXT_URL` blocks,
+/// with `PREV_TITLE` and `NEXT_TITLE` slots for the linked pages' titles.
+fn expand_neighbours(html: &str, neighbours: &Neighbours) -> String {
+    let mut html = html.to_string();
+    for (name, page) in [("PREV", &neighbours.prev), ("NEXT", &neighbours.next)] {
+        let title = page.as_ref().map(|p| p.frontmatter.title.as_str());
+        html = html.replace(&format!("@@@SLOT_{}_TITLE@@@", name), title.unwrap_or(""));
+        let url = page.as_ref().map(|p| p.url.as_str());
+        html = expand_if_block(&html, &format!("{}_URL", name), url);
+    }
+    html
+}
+
+/// Expands `<!-- @@@IF_{name}@@@ -->...<!-- @@@ENDIF@@@ -->` blocks, replacing
+/// `@@@SLOT_{name}@@@` inside them with `value`,
+/// or removes the blocks (keeping their `ELSE` part, if any) if there is no value.
+fn expand_if_block(html: &str, name: &str, value: Option<&str>) -> String {
+    let r = RegexBuilder::new(&format!(
+        r#"<!-- @@@IF_{}@@@ -->(.*?)<!-- @@@ENDIF@@@ -->"#,
+        name
+    ))
+    .dot_matches_new_line(true)
+    .build()
+    .unwrap();
+    let slot = format!("@@@SLOT_{}@@@", name);
+    r.replace_all(html, |caps: &Captures| {
+        let (then, otherwise) = split_else(caps.get(1).unwrap().as_str());
+        match value {
+            Some(value) => then.replace(&slot, value),
+            None => otherwise.to_string(),
+        }
+    })
+    .into_owned()
+}
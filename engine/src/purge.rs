@@ -0,0 +1,253 @@
+/*!
+ * Removal of CSS rules a page doesn't use.
+ */
+
+use std::collections::HashSet;
+
+use regex::Regex;
+
+/// Patterns for picking selectors apart
+#[derive(Debug)]
+struct SelectorPatterns {
+    parens: Regex,
+    ignored: Regex,
+    classes: Regex,
+    ids: Regex,
+    tags: Regex,
+}
+
+impl SelectorPatterns {
+    fn new() -> Self {
+        Self {
+            parens: Regex::new(r"\([^()]*\)").unwrap(),
+            ignored: Regex::new(r"\[[^\]]*\]|::?[a-zA-Z-]+").unwrap(),
+            classes: Regex::new(r"\.(-?[_a-zA-Z][_a-zA-Z0-9-]*)").unwrap(),
+            ids: Regex::new(r"#(-?[_a-zA-Z][_a-zA-Z0-9-]*)").unwrap(),
+            tags: Regex::new(r"(?:^|[\s>+~])([a-zA-Z][a-zA-Z0-9-]*)").unwrap(),
+        }
+    }
+}
+
+/// Class names, ids and tag names used by a page
+#[derive(Debug)]
+pub struct UsedSelectors {
+    classes: HashSet<String>,
+    ids: HashSet<String>,
+    tags: HashSet<String>,
+    // Class names and ids that are always kept, `*` at the end matching any suffix
+    safelist: Vec<String>,
+    patterns: SelectorPatterns,
+}
+
+impl UsedSelectors {
+    /// Collects the selectors used by an HTML document.
+    pub fn from_html(html: &str, safelist: &[String]) -> Self {
+        let attr = |name: &str| {
+            Regex::new(&format!(
+                r#"(?i)\s{}\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+))"#,
+                name
+            ))
+            .unwrap()
+        };
+        let values = |r: &Regex| {
+            r.captures_iter(html)
+                .filter_map(|c| c.get(1).or_else(|| c.get(2)).or_else(|| c.get(3)))
+                .flat_map(|m| m.as_str().split_whitespace())
+                .map(str::to_string)
+                .collect::<HashSet<_>>()
+        };
+        let tags = Regex::new(r"<([a-zA-Z][a-zA-Z0-9-]*)").unwrap();
+        Self {
+            classes: values(&attr("class")),
+            ids: values(&attr("id")),
+            tags: tags
+                .captures_iter(html)
+                .map(|c| c[1].to_ascii_lowercase())
+                .collect(),
+            safelist: safelist.to_vec(),
+            patterns: SelectorPatterns::new(),
+        }
+    }
+
+    fn is_safelisted(&self, name: &str) -> bool {
+        self.safelist.iter().any(|s| match s.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => s == name,
+        })
+    }
+
+    /// Whether a selector may match something in the page.
+    ///
+    /// Only the classes, ids and tags in it are checked, so e.g. `nav a`
+    /// is kept if there is any `nav` and any `a`.
+    /// Pseudo-classes and attribute selectors are ignored.
+    fn may_match(&self, selector: &str) -> bool {
+        let p = &self.patterns;
+        // drop the arguments of pseudo-classes (nested ones too),
+        // then attribute selectors and pseudo-classes themselves
+        let mut selector = selector.to_string();
+        while p.parens.is_match(&selector) {
+            selector = p.parens.replace_all(&selector, "").into_owned();
+        }
+        let selector = p.ignored.replace_all(&selector, " ");
+
+        p.classes
+            .captures_iter(&selector)
+            .all(|c| self.classes.contains(&c[1]) || self.is_safelisted(&c[1]))
+            && p.ids
+                .captures_iter(&selector)
+                .all(|c| self.ids.contains(&c[1]) || self.is_safelisted(&c[1]))
+            && p.tags
+                .captures_iter(&selector)
+                .all(|c| self.tags.contains(&c[1].to_ascii_lowercase()))
+    }
+}
+
+/// At-rules whose blocks contain rules that are purged as well
+const NESTED_AT_RULES: &[&str] = &["@media", "@supports", "@document", "@layer", "@container"];
+
+/// Removes the rules of a stylesheet that can't match anything in the page.
+///
+/// Comments are removed, and at-rules like `@font-face` and `@keyframes`
+/// are kept as is.
+pub fn purge(css: &str, used: &UsedSelectors) -> String {
+    let mut out = String::new();
+    let mut rest = css;
+    while let Some((prelude, body, after)) = next_rule(rest) {
+        let prelude = prelude.trim();
+        rest = after;
+        match body {
+            // a statement like @import
+            None => {
+                if !prelude.is_empty() {
+                    out.push_str(prelude);
+                    out.push(';');
+                }
+            }
+            Some(body) if NESTED_AT_RULES.iter().any(|a| starts_with_word(prelude, a)) => {
+                let inner = purge(body, used);
+                if !inner.is_empty() {
+                    out.push_str(&format!("{}{{{}}}", prelude, inner));
+                }
+            }
+            Some(body) if prelude.starts_with('@') => {
+                out.push_str(&format!("{}{{{}}}", prelude, body));
+            }
+            Some(body) => {
+                let selectors = split_selectors(prelude)
+                    .into_iter()
+                    .filter(|s| used.may_match(s))
+                    .collect::<Vec<_>>();
+                if !selectors.is_empty() {
+                    out.push_str(&format!("{}{{{}}}", selectors.join(","), body));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Whether `s` starts with the at-keyword `word`.
+fn starts_with_word(s: &str, word: &str) -> bool {
+    s.strip_prefix(word)
+        .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '-'))
+}
+
+/// Splits off the next rule or statement of a stylesheet, skipping comments.
+///
+/// Returns its prelude, its block's contents (none for a statement ending
+/// with `;`) and the rest of the stylesheet.
+fn next_rule(css: &str) -> Option<(String, Option<&str>, &str)> {
+    let mut prelude = String::new();
+    let mut chars = css.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '/' if css[i..].starts_with("/*") => {
+                let end = css[i + 2..].find("*/").map_or(css.len(), |e| i + 2 + e + 2);
+                while chars.peek().is_some_and(|(j, _)| *j < end) {
+                    chars.next();
+                }
+            }
+            '"' | '\'' => {
+                let end = string_end(css, i);
+                prelude.push_str(&css[i..end]);
+                while chars.peek().is_some_and(|(j, _)| *j < end) {
+                    chars.next();
+                }
+            }
+            ';' => return Some((prelude, None, &css[i + 1..])),
+            '{' => {
+                let end = block_end(css, i + 1);
+                let after = css.get(end + 1..).unwrap_or("");
+                return Some((prelude, Some(&css[i + 1..end]), after));
+            }
+            c => prelude.push(c),
+        }
+    }
+    if prelude.trim().is_empty() {
+        None
+    } else {
+        Some((prelude, None, ""))
+    }
+}
+
+/// Index right after the string starting at `start`.
+fn string_end(css: &str, start: usize) -> usize {
+    let quote = css[start..].chars().next().unwrap();
+    let mut escaped = false;
+    for (i, c) in css[start + 1..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return start + 1 + i + 1,
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+/// Index of the `}` closing the block whose contents start at `start`.
+fn block_end(css: &str, start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < css.len() {
+        let c = css[i..].chars().next().unwrap();
+        match c {
+            '"' | '\'' => {
+                i = string_end(css, i);
+                continue;
+            }
+            '/' if css[i..].starts_with("/*") => {
+                i = css[i + 2..].find("*/").map_or(css.len(), |e| i + 2 + e + 2);
+                continue;
+            }
+            '{' => depth += 1,
+            '}' if depth == 0 => return i,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    css.len()
+}
+
+/// Splits a selector list on its top-level commas.
+fn split_selectors(prelude: &str) -> Vec<&str> {
+    let mut selectors = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in prelude.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                selectors.push(prelude[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    selectors.push(prelude[start..].trim());
+    selectors.retain(|s| !s.is_empty());
+    selectors
+}
@@ -1,133 +1,346 @@
 use std::{
-    collections::{HashMap, HashSet},
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     path::{Path, PathBuf},
 };
 
-use dashmap::DashSet;
-use pulldown_cmark::{escape, Event, LinkType, Tag};
-use regex::{Captures, Regex, RegexBuilder};
-use syntect::{highlighting::Theme, parsing::SyntaxSet};
+use pulldown_cmark::{escape, CodeBlockKind, Event, LinkType, Tag};
+use regex::{Captures, Regex};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    html::{
+        append_highlighted_html_for_styled_line, start_highlighted_html_snippet, ClassStyle,
+        ClassedHTMLGenerator, IncludeBackground,
+    },
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
 use tracing::{event, instrument, Level};
 use url::Url;
 
-use crate::process::RenderingInput;
-use crate::{config::ResolvedConfig, frontmatter::Frontmatter};
+use crate::balance::TagBalancer;
+use crate::config::HighlightStyle;
+use crate::config::{AccessibilityLint, ResolvedConfig, ResolvedRootsConfig};
+use crate::emoji;
+use crate::footnotes::Footnotes;
+use crate::process::{
+    find_theme, syntax_class, truncate_excerpt, RenderingInput, CHILDREN_SLOT, POST_LIST_SLOT,
+};
+use crate::sanitize::Sanitizer;
+use crate::toc::{self, TableOfContents};
+use crate::util::{to_native_path, PathHelper};
+
+fn escape_attr(s: &str) -> String {
+    let mut escaped = String::new();
+    escape::escape_html(&mut escaped, s).unwrap();
+    escaped
+}
 
 pub struct RenderAdapter<'a, 'b, 'c: 'a, I: Iterator<Item = Event<'b>>> {
     ctx: &'a mut ProcessorContext<'a, 'c>,
     iter: I,
+    // Events read ahead of the current one
+    lookahead: VecDeque<Event<'b>>,
+    // Events already processed, returned before anything else
+    ready: VecDeque<Event<'b>>,
     // Table of contents
-    // level, title, slug
-    toc: Vec<(usize, String, String)>,
-    // Cache for header slugification
-    slugs_cache: HashMap<String, usize>,
-    // Extracted and parsed front matter, if any
-    pub(crate) frontmatter: Option<Frontmatter>,
-    // Frontmatter parsing state
-    frontmatter_state: FrontmatterParsingState,
+    toc: TableOfContents,
+    // Footnotes, rendered at the end
+    footnotes: Footnotes<'b>,
+    // Plain text content of the page
+    text: String,
+    // Where the current paragraph starts in `text`, until it ends
+    paragraph_start: Option<usize>,
+    // Plain text of the first paragraph
+    excerpt: Option<String>,
+    // Where the `<!-- more -->` marker is in `text`
+    more: Option<usize>,
+    // URL and alt text of the image we are inside of
+    image: Option<(String, String)>,
+    // URLs of images without alt text
+    missing_alt: Vec<String>,
+    // URLs of the PNG or JPEG fallbacks of WebP images, by the WebP URL
+    fallbacks: HashMap<String, String>,
+    // Caption of the figure we are inside of
+    figure: Option<String>,
+    // Raw HTML sanitization
+    sanitizer: Sanitizer,
+    // Raw HTML tag balancing
+    balancer: TagBalancer,
+    // Themes of the code blocks highlighted with classes
+    code_themes: BTreeSet<String>,
+    // Links and images pointing nowhere, with why
+    broken_links: Vec<String>,
+    // Fragments linked to, with the source of the page they should be in
+    anchors: Vec<(PathBuf, String)>,
 }
 
-#[derive(Debug)]
-enum FrontmatterParsingState {
-    // Waiting for frontmatter
-    Ready,
-    // Currently parsing frontmatter
-    Parsing(String),
-    // Done parsing frontmatter
-    Done,
-}
-
-const TOC_START: &'static str = r#"
-<section class="toc">
-    <h1>Table of contents</h1>
-"#;
-
-const TOC_END: &'static str = r#"
-</section>
-"#;
-
 impl<'a, 'b, 'c: 'a, I: Iterator<Item = Event<'b>>> RenderAdapter<'a, 'b, 'c, I> {
     pub fn new(iter: I, ctx: &'a mut ProcessorContext<'a, 'c>) -> Self {
         Self {
             iter,
             ctx,
-            toc: Vec::new(),
-            slugs_cache: HashMap::new(),
-            frontmatter: None,
-            frontmatter_state: FrontmatterParsingState::Ready,
+            lookahead: VecDeque::new(),
+            ready: VecDeque::new(),
+            toc: TableOfContents::new(),
+            footnotes: Footnotes::new(),
+            text: String::new(),
+            paragraph_start: None,
+            excerpt: None,
+            more: None,
+            image: None,
+            missing_alt: Vec::new(),
+            fallbacks: HashMap::new(),
+            figure: None,
+            sanitizer: Sanitizer::new(),
+            balancer: TagBalancer::new(),
+            code_themes: BTreeSet::new(),
+            broken_links: Vec::new(),
+            anchors: Vec::new(),
+        }
+    }
+
+    /// Peeks at the `n`th upcoming event.
+    fn peek(&mut self, n: usize) -> Option<&Event<'b>> {
+        while self.lookahead.len() <= n {
+            self.lookahead.push_back(self.iter.next()?);
         }
+        self.lookahead.get(n)
+    }
+
+    /// The title of the image that makes up the paragraph
+    /// about to start, if it has one.
+    fn lone_image_title(&mut self) -> Option<String> {
+        let title = match self.peek(0) {
+            Some(Event::Start(Tag::Image(_, _, title))) if !title.is_empty() => title.to_string(),
+            _ => return None,
+        };
+        let mut i = 1;
+        loop {
+            match self.peek(i)? {
+                Event::End(Tag::Image(..)) => break,
+                _ => i += 1,
+            }
+        }
+        match self.peek(i + 1) {
+            Some(Event::End(Tag::Paragraph)) => Some(title),
+            _ => None,
+        }
+    }
+
+    /// Takes the URLs of images that have no alt text.
+    ///
+    /// Always empty unless the accessibility lint is enabled.
+    pub fn take_missing_alt(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.missing_alt)
     }
 
-    // Converts a header title into a slug.
-    fn header_slug(&mut self, title: &str) -> String {
-        let fixed_up = title
-            .to_lowercase()
-            .replace(" ", "-")
-            .replace(|c: char| !c.is_alphanumeric() && c != '-', "");
-        if self.slugs_cache.contains_key(&fixed_up) {
-            self.slugs_cache
-                .insert(fixed_up.clone(), self.slugs_cache[&fixed_up] + 1);
-            format!("{}{}", fixed_up, self.slugs_cache[&fixed_up])
-        } else {
-            self.slugs_cache.insert(fixed_up.clone(), 0);
-            format!("{}", fixed_up)
+    /// Closes the elements raw HTML left open, if balancing is enabled.
+    pub fn finish_raw_html(&mut self) -> String {
+        let (closing, problems) = self.balancer.finish();
+        self.warn_malformed_html(problems);
+        closing
+    }
+
+    fn warn_malformed_html(&self, problems: Vec<String>) {
+        let filename = self.ctx.filename;
+        for problem in problems {
+            event!(Level::WARN, r#type = "malformed_html", ?filename, %problem);
         }
     }
 
-    /// Post processes syntax highlighting for code blocks
-    /// and adds "code" to styles if necessary
-    pub fn postprocess_syntax_highlighting(&mut self, inp: &str) -> String {
-        let r = RegexBuilder::new(
-            r#"<pre><code(?: class="language-(?P<language>[^\n]+?)")?>(?P<code>.*?)</code></pre>"#,
-        )
-        .dot_matches_new_line(true)
-        .build()
-        .unwrap();
-        let r2 = Regex::new(r#"<pre(.*)>\n"#).unwrap();
+    /// Takes the links and images that point nowhere, with why.
+    pub fn take_broken_links(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.broken_links)
+    }
+
+    /// Takes the fragments linked to, with the source of the page
+    /// each should be in.
+    pub fn take_anchors(&mut self) -> Vec<(PathBuf, String)> {
+        std::mem::take(&mut self.anchors)
+    }
+
+    /// Takes the plain text content seen so far.
+    ///
+    /// Code blocks are left out.
+    pub fn take_text(&mut self) -> String {
+        let text = std::mem::take(&mut self.text);
+        text.trim_end().to_string()
+    }
+
+    /// Takes the excerpt of the page, if any.
+    ///
+    /// This is all the text before a `<!-- more -->` marker,
+    /// or else the first paragraph shortened to the configured length.
+    pub fn take_excerpt(&mut self) -> Option<String> {
+        let excerpt = match self.more.take() {
+            Some(more) => truncate_excerpt(&self.text[..more], usize::MAX),
+            None => truncate_excerpt(&self.excerpt.take()?, self.ctx.config.excerpt_length),
+        };
+        Some(excerpt).filter(|e| !e.is_empty())
+    }
+
+    /// Takes the themes of the code blocks highlighted with classes, sorted.
+    pub fn take_code_themes(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.code_themes).into_iter().collect()
+    }
+
+    /// Highlights a code block, given its kind and contents,
+    /// and adds "code" to styles.
+    ///
+    /// A `theme=...` parameter after the language picks another theme.
+    fn highlight_code_block(&mut self, kind: &CodeBlockKind, code: &str) -> String {
+        self.ctx.styles.insert("code");
+        let mut words = match kind {
+            CodeBlockKind::Fenced(info) => info.split_whitespace(),
+            CodeBlockKind::Indented => "".split_whitespace(),
+        };
+        let language_token = words.next().unwrap_or("none");
+        let default = (self.ctx.config.theme.as_str(), self.ctx.theme);
+        let (theme_name, theme) = match words.find_map(|w| w.strip_prefix("theme=")) {
+            Some(name) => find_theme(self.ctx.extra_themes, name).unwrap_or_else(|| {
+                event!(
+                    Level::WARN,
+                    r#type = "unknown_theme",
+                    theme = name,
+                    path = ?self.ctx.filename
+                );
+                default
+            }),
+            None => default,
+        };
         let ss = self.ctx.ss;
-        let theme = self.ctx.theme;
-        r.replace_all(inp, |caps: &Captures| {
-            self.ctx.styles.insert("code");
-            let language_token = caps.name("language").map(|m| m.as_str()).unwrap_or("none");
-            let text = &caps
-                .name("code")
-                .unwrap()
-                .as_str()
-                .replace("&lt;", "<")
-                .replace("&gt;", ">")
-                .replace("&quot;", "\"");
-            let syntax = ss
-                .find_syntax_by_token(language_token)
-                .unwrap_or_else(|| ss.find_syntax_plain_text());
-            let highlighted = syntect::html::highlighted_html_for_string(text, &ss, syntax, theme);
-            let highlighted = r2
-                .replace_all(&highlighted, |caps: &Captures| {
-                    format!(
-                        r#"<pre{0}><code class="language-{1}">"#,
-                        &caps[1], language_token
-                    )
-                })
-                .replace("</pre>", "</code></pre>");
-            highlighted
+        let syntax = ss
+            .find_syntax_by_token(language_token)
+            .unwrap_or_else(|| ss.find_syntax_plain_text());
+        let language = escape_attr(language_token);
+        if self.ctx.config.highlight_style == HighlightStyle::Classes {
+            let mut generator =
+                ClassedHTMLGenerator::new_with_class_style(syntax, ss, ClassStyle::Spaced);
+            for line in LinesWithEndings::from(code) {
+                generator.parse_html_for_line_which_includes_newline(line);
+            }
+            self.code_themes.insert(theme_name.to_string());
+            return format!(
+                "<pre class=\"{}\"><code class=\"language-{}\">{}</code></pre>\n",
+                syntax_class(theme_name),
+                language,
+                generator.finalize()
+            );
+        }
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        let (pre, background) = start_highlighted_html_snippet(theme);
+        let mut html = format!(r#"{}<code class="language-{}">"#, pre.trim_end(), language);
+        for line in LinesWithEndings::from(code) {
+            let regions = highlighter.highlight(line, ss);
+            append_highlighted_html_for_styled_line(
+                &regions,
+                IncludeBackground::IfDifferent(background),
+                &mut html,
+            );
+        }
+        html.push_str("</code></pre>\n");
+        html
+    }
+
+    /// Gives a heading an id and adds it to the TOC, given its level
+    /// once its start has been processed.
+    ///
+    /// The rest of the heading is processed and queued, and the id is a slug
+    /// of its text, or a trailing `{#id}` which is removed.
+    /// Returns the heading's start tag.
+    fn heading(&mut self, level: u32) -> Event<'b> {
+        let mut inner = Vec::new();
+        let mut text = String::new();
+        for item in self.by_ref() {
+            match item {
+                Event::End(Tag::Heading(..)) => {
+                    inner.push(item);
+                    break;
+                }
+                Event::Text(ref s) | Event::Code(ref s) => text.push_str(s),
+                _ => {}
+            }
+            inner.push(item);
+        }
+
+        let r_id = Regex::new(r"\s*\{#([A-Za-z0-9_:.\-]+)\}\s*$").unwrap();
+        let id = r_id
+            .captures(&text)
+            .map(|id| (id[0].len(), id[1].to_string()))
+            .filter(|(len, _)| strip_text_suffix(&mut inner, *len));
+        let slug = match id {
+            Some((len, id)) => {
+                text.truncate(text.len() - len);
+                id
+            }
+            None if self.ctx.config.slug_transliterate => toc::slugify_ascii(&text),
+            None => toc::slugify(&text),
+        };
+        let slug = self.toc.unique(slug);
+        self.toc
+            .push(level as usize, text.trim().to_string(), slug.clone());
+        self.ready.extend(inner);
+        Event::Html(format!(r#"<h{} id="{}">"#, level, escape_attr(&slug)).into())
+    }
+
+    /// Wraps tables in a horizontally scrolling container
+    /// and adds "table" to styles if necessary
+    pub fn wrap_tables(&mut self, inp: &str) -> String {
+        if !inp.contains("<table>") {
+            return inp.to_string();
+        }
+        self.ctx.styles.insert("table");
+        inp.replace("<table>", r#"<div class="table-wrapper"><table>"#)
+            .replace("</table>", "</table></div>")
+    }
+
+    /// Wraps images that have a fallback in a `<picture>`
+    /// that only offers the WebP version to clients supporting it.
+    pub fn setup_image_fallbacks(&mut self, inp: &str) -> String {
+        if self.fallbacks.is_empty() {
+            return inp.to_string();
+        }
+        let r = Regex::new(r#"<img ([^>]*?)src="([^"]*)"([^>]*)>"#).unwrap();
+        r.replace_all(inp, |caps: &Captures| match self.fallbacks.get(&caps[2]) {
+            Some(fallback) => format!(
+                r#"<picture><source srcset="{}" type="image/webp"><img {}src="{}"{}></picture>"#,
+                &caps[2], &caps[1], fallback, &caps[3]
+            ),
+            None => caps[0].to_string(),
         })
         .into_owned()
     }
 
-    /// Sets up header links so that the TOC can be generated.
-    pub fn setup_header_links(&mut self, inp: &str) -> String {
-        let r = Regex::new(r"<h(\d)>(.*?)</h\d>").unwrap();
-        r.replace_all(inp, |caps: &Captures| {
-            let level = caps[1]
-                .parse::<usize>()
-                .expect("Only numbers can be parsed here");
-            let text = &caps[2];
-            let slug = self.header_slug(&text);
-            self.toc.push((level, text.to_string(), slug.clone()));
-            format!(r#"<h{0} id="{1}">{2}</h{0}>"#, level, slug, text)
+    /// Adds lazy loading and async decoding attributes to images
+    /// if enabled in the config.
+    pub fn setup_image_attributes(&mut self, inp: &str) -> String {
+        if !self.ctx.config.lazy_load_images {
+            return inp.to_string();
+        }
+        let r = Regex::new(r"<img ").unwrap();
+        let mut eager = self.ctx.config.eager_first_image;
+        r.replace_all(inp, |_: &Captures| {
+            if std::mem::take(&mut eager) {
+                r#"<img decoding="async" "#
+            } else {
+                r#"<img loading="lazy" decoding="async" "#
+            }
         })
         .into_owned()
     }
 
+    /// Renders the footnotes section
+    /// and adds "footnotes" to the styles if necessary
+    pub fn render_footnotes(&mut self) -> String {
+        if self.footnotes.is_empty() {
+            return String::new();
+        }
+        self.ctx.styles.insert("footnotes");
+        self.footnotes.render()
+    }
+
     /// Renders the table of contents
     /// and adds "toc" to the styles if necessary
     pub fn render_toc(&mut self) -> String {
@@ -136,37 +349,7 @@ impl<'a, 'b, 'c: 'a, I: Iterator<Item = Event<'b>>> RenderAdapter<'a, 'b, 'c, I>
         }
         self.ctx.styles.insert("toc");
         self.ctx.styles.insert("link");
-        let mut s = String::new();
-        s.push_str(TOC_START);
-        let mut last_level = 0;
-        for (level, title, slug) in std::mem::take(&mut self.toc) {
-            if level > last_level {
-                s.push_str("<ol>");
-            }
-            if level < last_level {
-                s.push_str("</ol>");
-            }
-            let escaped_slug = {
-                let mut escaped = String::new();
-                escape::escape_href(&mut escaped, &slug).unwrap();
-                escaped
-            };
-            let escaped_title = {
-                let mut escaped = String::new();
-                escape::escape_html(&mut escaped, &title).unwrap();
-                escaped
-            };
-            s.push_str(&format!(
-                "<li><a href=\"#{}\">{}</a></li>",
-                escaped_slug, escaped_title
-            ));
-            last_level = level;
-        }
-        for _ in 0..last_level {
-            s.push_str("</ol>");
-        }
-        s.push_str(TOC_END);
-        s
+        self.toc.render(&self.ctx.config.toc_title)
     }
 }
 
@@ -175,169 +358,371 @@ impl<'a, 'b, 'c: 'a, I: Iterator<Item = Event<'b>>> Iterator for RenderAdapter<'
 
     #[instrument(name = "process", skip(self))]
     fn next(&mut self) -> Option<Self::Item> {
-        let mut item = self.iter.next()?;
-        let styles = &mut self.ctx.styles;
-        let new_stack = &mut *self.ctx.new_stack;
-        let render_stack = self.ctx.render_stack;
-        let finished = self.ctx.finished;
-        let out_dir = &self.ctx.config.roots.output;
-        let base_dir = &self.ctx.config.roots.source;
-        let filename = self.ctx.filename;
-        if let Event::Rule = item {
-            // Start frontmatter parsing
-            use FrontmatterParsingState::*;
-            if let Ready = self.frontmatter_state {
-                // println!("starting frontmatter parsing");
-                self.frontmatter_state = Parsing(String::new());
-            } else if let Parsing(ref s) = self.frontmatter_state {
-                // Finish parsing
-                if !s.is_empty() {
-                    // println!("Parsing front matter: {}", s);
-                    let r = Frontmatter::parse_from_str(&s);
-                    match r {
-                        Ok(r) => {
-                            println!("Parsed front matter: {:#?}", r);
-                            self.frontmatter = Some(r);
-                        }
-                        Err(e) => {
-                            println!("Error parsing front matter: {}", e);
-                            self.frontmatter = None;
-                        }
-                    }
+        if let Some(item) = self.ready.pop_front() {
+            return Some(item);
+        }
+        let mut item = match self.lookahead.pop_front() {
+            Some(item) => item,
+            None => self.iter.next()?,
+        };
+        if let Event::Html(ref s) = item {
+            if is_more_marker(s) {
+                self.more.get_or_insert(self.text.len());
+                if !self.ctx.config.markdown.raw_html {
+                    // not worth showing as text
+                    return self.next();
                 }
-                self.frontmatter_state = Done;
             }
         }
-        if let Event::End(Tag::Heading(..)) = item {
-            use FrontmatterParsingState::*;
-            // End frontmatter parsing if needed
-            if let Parsing(ref s) = self.frontmatter_state {
-                // Finish parsing
-                if !s.is_empty() {
-                    // println!("Parsing front matter: {}", s);
-                    let r = Frontmatter::parse_from_str(&s);
-                    match r {
-                        Ok(r) => {
-                            println!("Parsed front matter: {:#?}", r);
-                            self.frontmatter = Some(r);
-                        }
-                        Err(e) => {
-                            println!("Error parsing front matter: {}", e);
-                            self.frontmatter = None;
-                        }
-                    }
+        if let Event::Html(s) = item {
+            item = if !self.ctx.config.markdown.raw_html {
+                Event::Text(s)
+            } else if self.ctx.config.markdown.sanitize_html {
+                Event::Html(self.sanitizer.sanitize(&s).into())
+            } else {
+                Event::Html(s)
+            };
+            if let Event::Html(ref mut s) = item {
+                if self.ctx.config.markdown.balance_html {
+                    let (balanced, problems) = self.balancer.balance(s);
+                    self.warn_malformed_html(problems);
+                    *s = balanced.into();
                 }
-                self.frontmatter_state = Done;
             }
+        } else if self.sanitizer.is_dropping() && matches!(item, Event::Text(..) | Event::Code(..))
+        {
+            // Inline content of e.g. a script
+            return self.next();
         }
-        if let Event::Text(ref s) = item {
-            if let FrontmatterParsingState::Parsing(ref mut ps) = self.frontmatter_state {
-                // println!("Frontmatter parsing got {}", s);
-                ps.push_str(s);
+        if let Event::Text(ref mut s) = item {
+            if self.ctx.config.emoji {
+                if let Cow::Owned(expanded) = emoji::expand(s) {
+                    *s = expanded.into();
+                }
             }
         }
-        if let Event::SoftBreak = item {
-            if let FrontmatterParsingState::Parsing(ref mut ps) = self.frontmatter_state {
-                ps.push('\n');
+        // Code blocks are highlighted as a whole,
+        // so their contents never reach the rest of the adapter
+        if let Event::Start(Tag::CodeBlock(ref kind)) = item {
+            let mut code = String::new();
+            loop {
+                match self.lookahead.pop_front().or_else(|| self.iter.next()) {
+                    Some(Event::Text(s)) => code.push_str(&s),
+                    Some(Event::End(Tag::CodeBlock(..))) | None => break,
+                    Some(_) => {}
+                }
             }
+            let html = self.highlight_code_block(kind, &code);
+            item = Event::Html(html.into());
         }
-        if let FrontmatterParsingState::Parsing(..) = self.frontmatter_state {
-            // Skip this element since front matter is being parsed
-            // This should eventually lead to the parsing ending.. therefore element get emitted
-            // TODO: does this blow the stack?
-            return self.next();
+        let styles = &mut self.ctx.styles;
+        let new_stack = &mut *self.ctx.new_stack;
+        let base_dir = &self.ctx.config.roots.source;
+        let filename = self.ctx.filename;
+        match item {
+            Event::Text(ref s) | Event::Code(ref s) => self.text.push_str(s),
+            Event::Start(Tag::Paragraph) => self.paragraph_start = Some(self.text.len()),
+            _ => {}
+        }
+        if let (Event::End(Tag::Paragraph), Some(start)) = (&item, self.paragraph_start) {
+            self.paragraph_start = None;
+            let paragraph = self.text[start..].trim();
+            if paragraph == CHILDREN_SLOT || paragraph == POST_LIST_SLOT {
+                // filled in later, so it isn't part of the page's text
+                self.text.truncate(start);
+            } else if self.excerpt.is_none() && !paragraph.is_empty() && paragraph != "[[TOC]]" {
+                // a TOC marker isn't much of an excerpt
+                self.excerpt = Some(paragraph.to_string());
+            }
+        }
+        match item {
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(Tag::Paragraph)
+            | Event::End(Tag::Heading(..))
+            | Event::End(Tag::Item)
+            | Event::End(Tag::TableCell)
+                if !self.text.is_empty() && !self.text.ends_with(' ') =>
+            {
+                self.text.push(' ')
+            }
+            _ => {}
         }
         if let Event::Start(Tag::Image(..)) = item {
             styles.insert("image");
         }
-        if let Event::Start(Tag::Image(LinkType::Inline, ref mut url, _)) = item {
-            if let Ok(parsed) = Url::parse(&url) {
-                use sha2::Digest;
-                let hashname = format!("{:x}", sha2::Sha256::digest(parsed.as_str().as_bytes()));
-                let new_url = format!("/images/{}.webp", hashname);
-                let input = RenderingInput::Image {
-                    input: parsed,
-                    output: hashname,
-                };
-                if !render_stack.contains(&input) && !finished.contains(&input) {
-                    render_stack.insert(input.clone());
+        if self.ctx.config.accessibility_lint != AccessibilityLint::Off {
+            match item {
+                Event::Start(Tag::Image(_, ref url, _)) => {
+                    self.image = Some((url.to_string(), String::new()))
+                }
+                Event::Text(ref s) | Event::Code(ref s) => {
+                    if let Some((_, alt)) = &mut self.image {
+                        alt.push_str(s);
+                    }
+                }
+                Event::End(Tag::Image(..)) => {
+                    if let Some((url, alt)) = self.image.take() {
+                        if alt.trim().is_empty() {
+                            event!(Level::WARN, r#type = "missing_alt", ?filename, %url);
+                            self.missing_alt.push(url);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Event::Start(Tag::Image(link_type, ref mut url, _)) = item {
+            let parsed = match Url::parse(url) {
+                Err(url::ParseError::RelativeUrlWithoutBase) => {
+                    let resolved = resolve_image(&self.ctx.config.roots, filename, url);
+                    if resolved.is_none() {
+                        event!(Level::WARN, r#type = "missing_image", ?filename, %url);
+                        self.broken_links.push(format!("no image at {}", url));
+                    }
+                    resolved
+                }
+                parsed => parsed.ok(),
+            };
+            if let (true, Some(parsed)) = (is_processed(link_type), parsed) {
+                let config = self.ctx.config;
+                let fallback = if config.image_fallback {
+                    let (input, path) = RenderingInput::fallback_image(parsed.clone());
                     new_stack.push(input);
+                    Some(config.site_url(&path))
+                } else {
+                    None
+                };
+                let (input, path) = RenderingInput::image(parsed);
+                new_stack.push(input);
+                let path = config.site_url(&path);
+                if let Some(fallback) = fallback {
+                    self.fallbacks.insert(path.clone(), fallback);
                 }
-                *url = new_url.into();
+                *url = path.into();
             }
         }
         if let Event::Start(Tag::Paragraph) = item {
             styles.insert("paragraph");
         }
         if let Event::Start(Tag::Heading(level)) = item {
-            match level {
-                1 => {
-                    styles.insert("h1");
-                }
-                _ => {}
+            if level == 1 {
+                styles.insert("h1");
             }
         }
         if let Event::Start(Tag::Link(..)) = item {
             styles.insert("link");
         }
-        if let Event::Start(Tag::Link(LinkType::Inline, ref mut url, _)) = item {
-            if let Ok(parsed) = Url::parse(&url) {
+        if let Event::Start(Tag::Link(_, ref url, _)) = item {
+            if let Some(fragment) = url.strip_prefix('#').filter(|f| !f.is_empty()) {
+                self.anchors
+                    .push((filename.to_path_buf(), fragment.to_string()));
+            }
+        }
+        if let Event::Start(Tag::Link(link_type, ref mut url, _)) = item {
+            if let (true, Ok(parsed)) = (is_processed(link_type), Url::parse(url)) {
                 // check if scheme is hyperref, if so add to stack and rewrite url
                 if parsed.scheme() == "hyperref" {
                     let parsed_path: &Path = parsed.path().as_ref();
                     let fname: PathBuf = if parsed_path.is_absolute() {
-                        out_dir.join(parsed_path.strip_prefix("/").unwrap())
+                        base_dir.join(parsed_path.strip_prefix("/").unwrap())
                     } else {
                         filename
                             .parent()
                             .unwrap_or("/".as_ref())
                             .join(parsed.path())
-                    }
-                    .with_extension("md");
-                    #[cfg(target_os = "windows")]
-                    // replace with backslashes so that \\?\ isn't broken
-                    let fname: PathBuf = fname.to_str().unwrap().replace("/", "\\").into();
-                    if let Ok(fname) = fname.canonicalize() {
-                        let fname_for_url = fname.strip_prefix(&base_dir).unwrap();
-                        #[cfg(target_os = "windows")]
-                        // windows is dumb again
-                        let fname_for_url: PathBuf =
-                            fname_for_url.to_str().unwrap().replace("\\", "/").into();
+                    };
+                    if let Some(fname) = resolve_input(&self.ctx.config.inputs.extensions, &fname) {
                         // figure out new location
-                        let new_location = format!(
-                            "/{}",
-                            fname_for_url.with_extension("html").to_str().unwrap(),
-                        );
-                        let input = RenderingInput::Page(fname);
-                        if !render_stack.contains(&input) && !finished.contains(&input) {
-                            match input {
-                                RenderingInput::Page(ref fname) => {
-                                    event!(Level::INFO, r#type = "walk", ?fname)
-                                }
-                                _ => {}
-                            }
-                            render_stack.insert(input.clone());
-                            new_stack.push(input);
+                        let config = self.ctx.config;
+                        let out_path = config.page_output_path(fname.maybe_unprefix(base_dir));
+                        let mut new_location = config.site_url(&config.page_url_path(&out_path));
+                        if let Some(fragment) = parsed.fragment() {
+                            new_location.push('#');
+                            new_location.push_str(fragment);
+                            self.anchors.push((fname.clone(), fragment.to_string()));
                         }
+                        new_stack.push(RenderingInput::Page(fname));
                         *url = new_location.into();
                     } else {
                         event!(Level::WARN, r#type = "invalid_hyperref", %url);
+                        self.broken_links.push(format!("no page for {}", url));
                     }
                 }
             }
         }
+        // Task lists get classes so they can be styled
+        match item {
+            Event::Start(Tag::List(None))
+                if matches!(self.peek(1), Some(Event::TaskListMarker(..))) =>
+            {
+                self.ctx.styles.insert("tasklist");
+                item = Event::Html("\n<ul class=\"task-list\">\n".into());
+            }
+            Event::Start(Tag::Item) => {
+                if let Some(&Event::TaskListMarker(checked)) = self.peek(0) {
+                    let class = if checked {
+                        "task-list-item checked"
+                    } else {
+                        "task-list-item"
+                    };
+                    item = Event::Html(format!("<li class=\"{}\">", class).into());
+                }
+            }
+            _ => {}
+        }
+        // Footnote definitions are moved to the end of the page
+        match item {
+            Event::FootnoteReference(ref name) => {
+                item = Event::Html(self.footnotes.reference(name).into());
+            }
+            Event::Start(Tag::FootnoteDefinition(ref name)) => {
+                self.footnotes.start_definition(name);
+                return self.next();
+            }
+            Event::End(Tag::FootnoteDefinition(..)) => {
+                self.footnotes.end_definition();
+                return self.next();
+            }
+            _ => {}
+        }
+        if self.footnotes.in_definition() {
+            self.footnotes.push(item);
+            return self.next();
+        }
+        if let Event::Start(Tag::Heading(level)) = item {
+            item = self.heading(level);
+        }
+        // Images with a title on their own are captioned with it
+        match item {
+            Event::Start(Tag::Paragraph) => {
+                if let Some(title) = self.lone_image_title() {
+                    self.ctx.styles.insert("figure");
+                    self.figure = Some(title);
+                    item = Event::Html("<figure>".into());
+                }
+            }
+            Event::Start(Tag::Image(link_type, ref url, _)) if self.figure.is_some() => {
+                item = Event::Start(Tag::Image(link_type, url.clone(), "".into()));
+            }
+            Event::End(Tag::Paragraph) => {
+                if let Some(caption) = self.figure.take() {
+                    item = Event::Html(
+                        format!(
+                            "<figcaption>{}</figcaption></figure>\n",
+                            escape_attr(&caption)
+                        )
+                        .into(),
+                    );
+                }
+            }
+            _ => {}
+        }
         Some(item)
     }
 }
 
+/// Whether links and images of this type are processed.
+///
+/// Reference-style links have already been resolved to their URL by the
+/// parser, so they are handled like inline ones.
+/// Autolinks and broken references are left alone.
+fn is_processed(link_type: LinkType) -> bool {
+    matches!(
+        link_type,
+        LinkType::Inline | LinkType::Reference | LinkType::Collapsed | LinkType::Shortcut
+    )
+}
+
+/// Whether raw HTML is a `<!-- more -->` excerpt marker.
+fn is_more_marker(html: &str) -> bool {
+    let r = Regex::new(r"^\s*<!--\s*more\s*-->\s*$").unwrap();
+    r.is_match(html)
+}
+
+/// Resolves a relative image path to an existing local file.
+///
+/// Paths starting with `/` are relative to the source root.
+/// Others are relative to the folder of the page,
+/// or else to the images root.
+fn resolve_image(roots: &ResolvedRootsConfig, filename: &Path, path: &str) -> Option<Url> {
+    match path.strip_prefix('/') {
+        Some(path) => local_file_url(&roots.source, path),
+        None => filename
+            .parent()
+            .and_then(|dir| local_file_url(dir, path))
+            .or_else(|| local_file_url(&roots.images, path)),
+    }
+}
+
+/// URL of an existing file at a relative URL path from `dir`.
+fn local_file_url(dir: &Path, path: &str) -> Option<Url> {
+    let dir = dir.canonicalize().ok()?;
+    Url::from_directory_path(dir)
+        .ok()?
+        .join(path)
+        .ok()
+        .filter(|url| url.to_file_path().is_ok_and(|p| p.is_file()))
+}
+
+/// Resolves a linked page to an existing source file.
+///
+/// If the link already has one of the configured input extensions it is
+/// used as is, otherwise each extension is tried in order.
+fn resolve_input(extensions: &[String], fname: &Path) -> Option<PathBuf> {
+    let has_extension = fname
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.iter().any(|x| x == e));
+    let candidates = if has_extension {
+        vec![fname.to_path_buf()]
+    } else {
+        extensions.iter().map(|e| fname.with_extension(e)).collect()
+    };
+    candidates
+        .into_iter()
+        .find_map(|fname| to_native_path(&fname).canonicalize().ok())
+}
+
+/// Removes the last `excess` bytes of text from a heading's events.
+///
+/// Only trailing text events are cut, and nothing is changed
+/// if that isn't enough (e.g. if the heading ends in inline code).
+fn strip_text_suffix(events: &mut [Event], mut excess: usize) -> bool {
+    let mut available = 0;
+    for event in events.iter().rev() {
+        match event {
+            Event::Text(s) => available += s.len(),
+            Event::End(Tag::Heading(..)) => {}
+            _ => break,
+        }
+        if available >= excess {
+            break;
+        }
+    }
+    if available < excess {
+        return false;
+    }
+    for event in events.iter_mut().rev() {
+        if excess == 0 {
+            break;
+        }
+        if let Event::Text(s) = event {
+            let keep = s.len().saturating_sub(excess);
+            excess -= s.len() - keep;
+            *s = s[..keep].to_string().into();
+        }
+    }
+    true
+}
+
 /// Processing context for a single file
 pub struct ProcessorContext<'a, 'b: 'a> {
     pub(crate) styles: &'a mut HashSet<&'b str>,
     pub(crate) filename: &'a Path,
     pub(crate) config: &'a ResolvedConfig,
-    pub(crate) finished: &'a DashSet<RenderingInput>,
-    pub(crate) render_stack: &'a DashSet<RenderingInput>,
     pub(crate) new_stack: &'a mut Vec<RenderingInput>,
     pub(crate) ss: &'a SyntaxSet,
     pub(crate) theme: &'a Theme,
+    pub(crate) extra_themes: &'a ThemeSet,
 }
@@ -0,0 +1,209 @@
+/*!
+ * Allowlist sanitization of raw HTML embedded in markdown.
+ */
+
+use regex::Regex;
+
+/// Tags that are kept
+const ALLOWED_TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "dd",
+    "del",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+    "var",
+];
+
+/// Tags that are removed together with their content
+const DROPPED_TAGS: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "noscript", "template",
+];
+
+/// Attributes that are kept on allowed tags
+const ALLOWED_ATTRIBUTES: &[&str] = &[
+    "alt", "class", "colspan", "dir", "height", "href", "id", "lang", "open", "rowspan", "src",
+    "title", "width",
+];
+
+/// Attributes holding URLs, whose scheme is checked
+const URL_ATTRIBUTES: &[&str] = &["href", "src"];
+
+/// URL schemes that are kept, relative URLs are always kept
+const ALLOWED_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+/// Sanitizes the raw HTML fragments of a page.
+///
+/// A fragment may open an element that a later one closes,
+/// so the same sanitizer has to be used for a whole page.
+#[derive(Default, Debug)]
+pub struct Sanitizer {
+    // Dropped element we are inside of
+    dropping: Option<String>,
+}
+
+impl Sanitizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether we are inside an element that is removed with its content.
+    pub fn is_dropping(&self) -> bool {
+        self.dropping.is_some()
+    }
+
+    /// Sanitizes a raw HTML fragment.
+    ///
+    /// Disallowed tags are removed but their content is kept,
+    /// except for scripts and the like which are removed entirely.
+    /// Comments are kept.
+    pub fn sanitize(&mut self, html: &str) -> String {
+        let r = Regex::new(
+            r#"(?s)<!--.*?-->|<(/?)([a-zA-Z][a-zA-Z0-9-]*)((?:[^>"']|"[^"]*"|'[^']*')*)>"#,
+        )
+        .unwrap();
+        let mut out = String::new();
+        let mut last = 0;
+        for caps in r.captures_iter(html) {
+            let m = caps.get(0).unwrap();
+            if self.dropping.is_none() {
+                out.push_str(&html[last..m.start()]);
+            }
+            last = m.end();
+            let name = match caps.get(2) {
+                Some(name) => name.as_str().to_ascii_lowercase(),
+                None => {
+                    // A comment
+                    if self.dropping.is_none() {
+                        out.push_str(m.as_str());
+                    }
+                    continue;
+                }
+            };
+            let closing = !caps[1].is_empty();
+            if let Some(dropping) = &self.dropping {
+                if closing && *dropping == name {
+                    self.dropping = None;
+                }
+                continue;
+            }
+            if DROPPED_TAGS.contains(&name.as_str()) {
+                let self_closing = caps[3].trim_end().ends_with('/');
+                if !closing && !self_closing {
+                    self.dropping = Some(name);
+                }
+                continue;
+            }
+            if !ALLOWED_TAGS.contains(&name.as_str()) {
+                continue;
+            }
+            if closing {
+                out.push_str(&format!("</{}>", name));
+            } else {
+                out.push_str(&format!("<{}{}>", name, sanitize_attributes(&caps[3])));
+            }
+        }
+        if self.dropping.is_none() {
+            out.push_str(&html[last..]);
+        }
+        out
+    }
+}
+
+/// Keeps the allowed attributes of a tag, normalized to double quotes.
+fn sanitize_attributes(attributes: &str) -> String {
+    let r = Regex::new(
+        r#"([a-zA-Z_:][-a-zA-Z0-9_:.]*)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+)))?"#,
+    )
+    .unwrap();
+    let mut out = String::new();
+    for caps in r.captures_iter(attributes) {
+        let name = caps[1].to_ascii_lowercase();
+        if !ALLOWED_ATTRIBUTES.contains(&name.as_str()) {
+            continue;
+        }
+        let value = caps
+            .get(2)
+            .or_else(|| caps.get(3))
+            .or_else(|| caps.get(4))
+            .map(|m| m.as_str());
+        match value {
+            Some(value) => {
+                if URL_ATTRIBUTES.contains(&name.as_str()) && !is_safe_url(value) {
+                    continue;
+                }
+                out.push_str(&format!(r#" {}="{}""#, name, value.replace('"', "&quot;")));
+            }
+            None => out.push_str(&format!(" {}", name)),
+        }
+    }
+    if attributes.trim_end().ends_with('/') {
+        out.push_str(" /");
+    }
+    out
+}
+
+/// Whether a URL is relative or uses an allowed scheme.
+///
+/// Whitespace and control characters are ignored like browsers do,
+/// and character references before the scheme are rejected
+/// since they could hide one.
+fn is_safe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    let prefix = url.split(['/', '?', '#']).next().unwrap();
+    if prefix.contains('&') {
+        return false;
+    }
+    match prefix.split_once(':') {
+        Some((scheme, _)) => ALLOWED_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()),
+        None => true,
+    }
+}
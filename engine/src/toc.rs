@@ -0,0 +1,136 @@
+/*!
+ * Table of contents generation and header slugs.
+ */
+
+use std::collections::HashMap;
+
+use pulldown_cmark::escape;
+use regex::Regex;
+
+/// Default heading of the table of contents
+pub const DEFAULT_TOC_TITLE: &str = "Table of contents";
+
+const TOC_START: &str = r#"
+<section class="toc">
+    <h1>@@@TOC_TITLE@@@</h1>
+"#;
+
+const TOC_END: &str = r#"
+</section>
+"#;
+
+/// Puts a rendered table of contents into `html`.
+///
+/// The TOC replaces the first `[[TOC]]` paragraph or `<!-- toc -->` comment,
+/// and any other markers are removed.
+/// If there is no marker, the TOC is put at the top.
+pub fn splice(html: &str, toc: &str) -> String {
+    let r = Regex::new(r"(?m)^(?:<p>\[\[TOC\]\]</p>|<!-- toc -->)\n?").unwrap();
+    if !r.is_match(html) {
+        return format!("{}{}", toc, html);
+    }
+    let mut toc = Some(toc);
+    r.replace_all(html, |_: &regex::Captures| toc.take().unwrap_or(""))
+        .into_owned()
+}
+
+/// Converts a header title into a slug, which may be taken already.
+pub fn slugify(title: &str) -> String {
+    title
+        .to_lowercase()
+        .replace(" ", "-")
+        .replace(|c: char| !c.is_alphanumeric() && c != '-', "")
+}
+
+/// Like `slugify`, but romanizes the title first so the slug is ASCII,
+/// e.g. "Café" becomes "cafe".
+pub fn slugify_ascii(title: &str) -> String {
+    slugify(deunicode::deunicode(title).trim())
+}
+
+/// Collects headers of a page and renders them as a table of contents
+#[derive(Default, Debug)]
+pub struct TableOfContents {
+    // level, title, slug
+    entries: Vec<(usize, String, String)>,
+    // Cache for header slugification
+    slugs_cache: HashMap<String, usize>,
+}
+
+impl TableOfContents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Converts a header title into a slug, unique within this page.
+    pub fn slug(&mut self, title: &str) -> String {
+        self.unique(slugify(title))
+    }
+
+    /// Makes a slug unique within this page by numbering repeats.
+    pub fn unique(&mut self, fixed_up: String) -> String {
+        if self.slugs_cache.contains_key(&fixed_up) {
+            self.slugs_cache
+                .insert(fixed_up.clone(), self.slugs_cache[&fixed_up] + 1);
+            format!("{}{}", fixed_up, self.slugs_cache[&fixed_up])
+        } else {
+            self.slugs_cache.insert(fixed_up.clone(), 0);
+            fixed_up
+        }
+    }
+
+    /// Adds a header to the table of contents
+    pub fn push(&mut self, level: usize, title: String, slug: String) {
+        self.entries.push((level, title, slug));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Renders the table of contents, consuming the collected headers.
+    ///
+    /// `title` is used as the heading and is HTML-escaped.
+    /// Returns an empty string if there are no headers.
+    pub fn render(&mut self, title: &str) -> String {
+        if self.entries.is_empty() {
+            return String::new();
+        }
+        let mut s = String::new();
+        let escaped_heading = {
+            let mut escaped = String::new();
+            escape::escape_html(&mut escaped, title).unwrap();
+            escaped
+        };
+        s.push_str(&TOC_START.replace("@@@TOC_TITLE@@@", &escaped_heading));
+        let mut last_level = 0;
+        for (level, title, slug) in std::mem::take(&mut self.entries) {
+            if level > last_level {
+                s.push_str("<ol>");
+            }
+            if level < last_level {
+                s.push_str("</ol>");
+            }
+            let escaped_slug = {
+                let mut escaped = String::new();
+                escape::escape_href(&mut escaped, &slug).unwrap();
+                escaped
+            };
+            let escaped_title = {
+                let mut escaped = String::new();
+                escape::escape_html(&mut escaped, &title).unwrap();
+                escaped
+            };
+            s.push_str(&format!(
+                "<li><a href=\"#{}\">{}</a></li>",
+                escaped_slug, escaped_title
+            ));
+            last_level = level;
+        }
+        for _ in 0..last_level {
+            s.push_str("</ol>");
+        }
+        s.push_str(TOC_END);
+        s
+    }
+}
@@ -1,4 +1,8 @@
-use std::path::{Path, PathBuf};
+/*!
+ * Path helpers.
+ */
+
+use std::path::{Component, Path, PathBuf};
 
 pub trait PathHelper {
     /// Attempts to canonicalize the path, otherwise
@@ -7,8 +11,10 @@ pub trait PathHelper {
     /// Attempts to join the given path with self,
     /// unless self is an absolute path.
     fn maybe_suffix(&self, p: &Path) -> PathBuf;
-    /// Attempts to remove the given prefix from self,
-    /// unless self is a relative path.
+    /// Removes the given prefix from self if self starts with it,
+    /// otherwise returns self as is.
+    ///
+    /// Only whole components are matched, so `/out` isn't a prefix of `/output`.
     fn maybe_unprefix(&self, p: &Path) -> &Path;
 }
 
@@ -24,10 +30,58 @@ impl PathHelper for Path {
         }
     }
     fn maybe_unprefix(&self, p: &Path) -> &Path {
-        if self.is_relative() {
-            self.strip_prefix(p).unwrap_or(self)
+        self.strip_prefix(p).unwrap_or(self)
+    }
+}
+
+/// Converts a path into a URL path, with `/` separators on every platform.
+///
+/// Windows drive prefixes are dropped, so this is meant for paths
+/// relative to one of the roots.
+pub fn to_url_path(path: &Path) -> String {
+    let parts = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            Component::ParentDir => Some("..".into()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if path.has_root() {
+        format!("/{}", parts.join("/"))
+    } else {
+        parts.join("/")
+    }
+}
+
+/// Converts a path that may contain `/` separators into the platform's own.
+///
+/// On Windows, `\\?\` paths (as returned by canonicalize) don't accept `/`.
+pub fn to_native_path(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        path.to_string_lossy().replace('/', "\\").into()
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Copies a folder and everything in it, keeping modification times
+/// so that copied outputs are exactly as fresh as the originals.
+pub fn copy_dir_keeping_times(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_keeping_times(&entry.path(), &dest)?;
         } else {
-            self
+            std::fs::copy(entry.path(), &dest)?;
+            let modified = entry.metadata()?.modified()?;
+            std::fs::File::options()
+                .write(true)
+                .open(&dest)?
+                .set_modified(modified)?;
         }
     }
+    Ok(())
 }
@@ -0,0 +1,101 @@
+//! Tests for the generated archive pages.
+
+use std::path::{Path, PathBuf};
+
+use engine::{BuildSummary, Config, EngineError, Processor, Result};
+
+fn site(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("engine-archive-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib").join("style-chunks")).unwrap();
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("lib").join("style-chunks").join("_global.css"),
+        "body { margin: 0; }",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib").join("prelude.html"),
+        "<head>@@@SLOT_STYLES@@@@@@SLOT_HEAD_SCRIPTS@@@</head>@@@SLOT_CONTENT@@@",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src").join("index.md"),
+        "---\ntitle: Home\ndate: 04/03/2021\n---\n\nWelcome.\n",
+    )
+    .unwrap();
+    dir
+}
+
+/// Builds the site, with `extra` config merged on top.
+fn build(dir: &Path, extra: &str) -> Result<BuildSummary> {
+    let cfg = Config::from_layers(&[
+        "[roots]\nsource = \"src\"\nlib = \"lib\"\nassets = \"assets\"\noutput = \"out\"\n\
+         [outputs]\ngenerate-archive = true",
+        extra,
+    ])
+    .unwrap()
+    .resolve(dir);
+    let processor = Processor::new(cfg).unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(processor.render_toplevel(false))
+}
+
+#[test]
+fn unchanged_archive_is_not_rewritten() {
+    let dir = site("unchanged");
+    let first = build(&dir, "").unwrap();
+    assert!(dir.join("out").join("archive").join("index.html").exists());
+    assert_eq!(first.pages.written, 2, "{:?}", first);
+    let second = build(&dir, "").unwrap();
+    assert_eq!(second.pages.written, 0, "{:?}", second);
+    assert_eq!(second.pages.fresh, 2, "{:?}", second);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn archive_conflicts_with_a_source_page() {
+    let dir = site("conflict");
+    std::fs::create_dir_all(dir.join("src").join("archive")).unwrap();
+    std::fs::write(
+        dir.join("src").join("archive").join("index.md"),
+        "---\ntitle: Archive\n---\n\nMine.\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src").join("index.md"),
+        "---\ntitle: Home\ndate: 04/03/2021\n---\n\n[Archive](hyperref:archive/index)\n",
+    )
+    .unwrap();
+    match build(&dir, "") {
+        Err(EngineError::OutputConflict { path, .. }) => {
+            assert!(path.ends_with("archive/index.html"), "{:?}", path);
+        }
+        other => panic!("expected an output conflict, got {:?}", other),
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn archive_urls_can_be_pretty() {
+    let dir = site("pretty");
+    std::fs::write(
+        dir.join("lib").join("prelude.html"),
+        "<head>@@@SLOT_STYLES@@@@@@SLOT_HEAD_SCRIPTS@@@\
+         <!-- @@@IF_CANONICAL@@@ --><link rel=\"canonical\" href=\"@@@SLOT_CANONICAL@@@\">\
+         <!-- @@@ENDIF@@@ --></head>@@@SLOT_CONTENT@@@",
+    )
+    .unwrap();
+    build(
+        &dir,
+        "base-url = \"https://example.com\"\n[outputs]\npretty-urls = true",
+    )
+    .unwrap();
+    let html = std::fs::read_to_string(dir.join("out").join("archive").join("index.html")).unwrap();
+    assert!(
+        html.contains(r#"href="https://example.com/archive/""#),
+        "{}",
+        html
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,75 @@
+//! Tests for listing what a build rendered.
+
+use std::path::Path;
+
+use engine::{Config, Processor};
+
+#[test]
+fn built_assets_are_listed() {
+    let dir = std::env::temp_dir().join(format!("engine-assets-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::create_dir_all(dir.join("src").join("posts")).unwrap();
+    std::fs::create_dir_all(dir.join("lib").join("style-chunks")).unwrap();
+    std::fs::write(
+        dir.join("lib").join("style-chunks").join("_global.css"),
+        "body { margin: 0; }",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib").join("prelude.html"),
+        "<head>@@@SLOT_STYLES@@@@@@SLOT_HEAD_SCRIPTS@@@</head>@@@SLOT_CONTENT@@@",
+    )
+    .unwrap();
+    let pixel = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/pixel.png");
+    std::fs::copy(pixel, dir.join("src").join("pixel.png")).unwrap();
+    std::fs::write(dir.join("src").join("app.js"), "console.log(1)").unwrap();
+    std::fs::write(
+        dir.join("src").join("index.md"),
+        "---\ntitle: Home\nscripts: [app.js]\n---\n\n![A pixel](pixel.png) [Post](hyperref:posts/a)\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src").join("posts").join("a.md"),
+        "---\ntitle: A\n---\n\nNothing here.\n",
+    )
+    .unwrap();
+
+    let cfg = Config::from_layers(&[
+        "[roots]\nsource = \"src\"\nlib = \"lib\"\nassets = \"assets\"\noutput = \"out\"",
+    ])
+    .unwrap()
+    .resolve(&dir);
+    let processor = Processor::new(cfg).unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(processor.clone().render_toplevel(false))
+        .unwrap();
+    let assets = processor.assets();
+
+    let out = dir.join("out");
+    let pages = assets
+        .pages
+        .iter()
+        .map(|p| p.output.strip_prefix(&out).unwrap().to_path_buf())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        pages,
+        [Path::new("index.html"), Path::new("posts/a.html")],
+        "{:?}",
+        assets
+    );
+    assert_eq!(assets.images.len(), 1, "{:?}", assets);
+    assert!(assets.images[0].source.path().ends_with("pixel.png"));
+    assert!(assets.images[0].output.starts_with(out.join("images")));
+    assert!(assets.images[0].output.exists());
+    assert!(assets.fonts.is_empty());
+    assert!(assets
+        .styles
+        .iter()
+        .any(|s| s.source == ["_global".to_string()]));
+    assert_eq!(assets.scripts.len(), 1, "{:?}", assets);
+    assert!(assets.scripts[0].source.ends_with("app.js"));
+    assert!(assets.scripts[0].output.exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
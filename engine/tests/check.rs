@@ -0,0 +1,83 @@
+//! Tests for checking links without building.
+
+use std::path::PathBuf;
+
+use engine::{Config, Processor};
+
+/// Writes a small site to a fresh temporary folder.
+fn site(name: &str, pages: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("engine-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(
+        dir.join("lib").join("prelude.html"),
+        r##"<a href="#main">Skip</a>@@@SLOT_STYLES@@@<main id="main">@@@SLOT_CONTENT@@@</main>"##,
+    )
+    .unwrap();
+    for (path, contents) in pages {
+        let path = dir.join("src").join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+fn check(dir: &std::path::Path) -> engine::CheckReport {
+    let cfg = Config::from_layers(&[
+        "[roots]\nsource = \"src\"\nlib = \"lib\"\nassets = \"assets\"\noutput = \"out\"",
+    ])
+    .unwrap()
+    .resolve(dir);
+    let processor = Processor::new(cfg).unwrap();
+    tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(processor.check())
+        .unwrap()
+}
+
+#[test]
+fn broken_links_are_reported() {
+    let dir = site(
+        "check",
+        &[
+            ("index.md", "# Home\n\n[ok](hyperref:posts/a#intro) [top](#home) [skip](#main)\n"),
+            (
+                "posts/a.md",
+                "# Intro\n\n[gone](hyperref:b) [anchor](hyperref:../index#nowhere) ![](missing.png)\n",
+            ),
+        ],
+    );
+    let report = check(&dir);
+    assert_eq!(report.pages, 2);
+    let reasons = report
+        .broken
+        .iter()
+        .map(|b| b.reason.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(reasons.len(), 3, "{:?}", reasons);
+    assert!(report.broken.iter().all(|b| b.page.ends_with("posts/a.md")));
+    assert!(
+        reasons.iter().any(|r| r.contains("hyperref:b")),
+        "{:?}",
+        reasons
+    );
+    assert!(
+        reasons.iter().any(|r| r.contains("missing.png")),
+        "{:?}",
+        reasons
+    );
+    assert!(
+        reasons.iter().any(|r| r.contains("#nowhere")),
+        "{:?}",
+        reasons
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn nothing_is_written() {
+    let dir = site("check-dry", &[("index.md", "# Home\n")]);
+    assert!(check(&dir).broken.is_empty());
+    assert!(!dir.join("out").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,159 @@
+//! Tests for config parsing and merging.
+
+use engine::Config;
+
+const BASE: &str = r#"
+base-path = "/site"
+theme = "Monokai"
+
+[roots]
+source = "src"
+lib = "lib"
+assets = "assets"
+output = "out"
+
+[inputs]
+build-all = true
+"#;
+
+#[test]
+fn later_layers_override_earlier_ones() {
+    let overlay = r#"
+    theme = "InspiredGitHub"
+
+    [roots]
+    output = "public"
+    "#;
+    let cfg = Config::from_layers(&[BASE, overlay]).unwrap();
+    assert_eq!(cfg.theme.as_deref(), Some("InspiredGitHub"));
+    assert_eq!(cfg.base_path.as_deref(), Some("/site"));
+    // tables are merged, not replaced
+    assert_eq!(cfg.roots.source, std::path::Path::new("src"));
+    assert_eq!(cfg.roots.output, std::path::Path::new("public"));
+    assert_eq!(cfg.inputs.and_then(|i| i.build_all), Some(true));
+}
+
+#[test]
+fn incomplete_layers_are_rejected() {
+    assert!(Config::from_layers(&["theme = \"Monokai\""]).is_err());
+    assert!(Config::from_layers(&[BASE, "[roots"]).is_err());
+}
+
+#[test]
+fn environment_variables_are_expanded() {
+    std::env::set_var("ENGINE_TEST_OUTPUT", "public");
+    let overlay = r#"
+    [roots]
+    output = "${ENGINE_TEST_OUTPUT}/site"
+    "#;
+    let cfg = Config::from_layers(&[BASE, overlay]).unwrap();
+    assert_eq!(cfg.roots.output, std::path::Path::new("public/site"));
+
+    let overlay = r#"base-url = "https://${ENGINE_TEST_UNSET}""#;
+    let err = Config::from_layers(&[BASE, overlay]).unwrap_err();
+    assert!(err.to_string().contains("ENGINE_TEST_UNSET"), "{}", err);
+}
+
+#[test]
+fn global_style_is_always_included_by_default() {
+    let dir = std::path::Path::new(".");
+    let cfg = Config::from_layers(&[BASE]).unwrap().resolve(dir);
+    assert_eq!(cfg.lib.styles.always_styles, ["_global"]);
+
+    let overlay = r#"
+    [lib.styles]
+    css = {}
+    always-styles = ["base", "fonts"]
+    "#;
+    let cfg = Config::from_layers(&[BASE, overlay]).unwrap().resolve(dir);
+    assert_eq!(cfg.lib.styles.always_styles, ["base", "fonts"]);
+}
+
+#[test]
+fn pretty_urls_give_pages_their_own_folder() {
+    use std::path::Path;
+    let dir = Path::new(".");
+    let cfg = Config::from_layers(&[BASE]).unwrap().resolve(dir);
+    let output = cfg.page_output_path(Path::new("blog/post.md"));
+    assert_eq!(output, Path::new("blog/post.html"));
+    assert_eq!(cfg.page_url_path(&output), "blog/post.html");
+
+    let overlay = "[outputs]\npretty-urls = true";
+    let cfg = Config::from_layers(&[BASE, overlay]).unwrap().resolve(dir);
+    let pages = [
+        ("blog/post.md", "blog/post/index.html", "blog/post/"),
+        ("index.md", "index.html", ""),
+        ("blog/index.md", "blog/index.html", "blog/"),
+    ];
+    for (source, output, url) in pages.iter() {
+        let actual = cfg.page_output_path(Path::new(source));
+        assert_eq!(actual, Path::new(output));
+        assert_eq!(cfg.page_url_path(&actual), *url);
+    }
+}
+
+#[test]
+fn always_build_defaults_to_no_patterns() {
+    let dir = std::path::Path::new(".");
+    let cfg = Config::from_layers(&[BASE]).unwrap().resolve(dir);
+    assert!(cfg.inputs.always_build.is_empty());
+
+    let overlay = "[inputs]\nalways-build = [\"posts/**/*.md\"]";
+    let cfg = Config::from_layers(&[BASE, overlay]).unwrap().resolve(dir);
+    assert_eq!(cfg.inputs.always_build, ["posts/**/*.md"]);
+}
+
+#[test]
+fn invalid_ignore_patterns_are_rejected() {
+    let dir = std::path::Path::new(".");
+    let overlay = "[inputs]\nignore = [\"drafts\", \"[oops\"]";
+    let cfg = Config::from_layers(&[BASE, overlay]).unwrap().resolve(dir);
+    assert_eq!(cfg.inputs.ignore, ["drafts", "[oops"]);
+    let err = engine::Processor::new(cfg).unwrap_err();
+    assert!(err.to_string().contains("[oops"), "{}", err);
+}
+
+#[test]
+fn small_outputs_are_only_checked_when_asked() {
+    use engine::config::SmallOutputs;
+    let dir = std::path::Path::new(".");
+    let cfg = Config::from_layers(&[BASE]).unwrap().resolve(dir);
+    assert_eq!(cfg.build.small_outputs, SmallOutputs::Off);
+    assert_eq!(cfg.build.min_output_size, 1);
+
+    let overlay = "[build]\nsmall-outputs = \"error\"\nmin-output-size = 512";
+    let cfg = Config::from_layers(&[BASE, overlay]).unwrap().resolve(dir);
+    assert_eq!(cfg.build.small_outputs, SmallOutputs::Error);
+    assert_eq!(cfg.build.min_output_size, 512);
+}
+
+#[test]
+fn concurrency_limits_are_at_least_one() {
+    let dir = std::path::Path::new(".");
+    let cfg = Config::from_layers(&[BASE]).unwrap().resolve(dir);
+    assert_eq!(cfg.build.fetch_concurrency, 16);
+    assert!(cfg.build.encode_concurrency >= 1);
+
+    let overlay = "[build]\nfetch-concurrency = 64\nencode-concurrency = 0";
+    let cfg = Config::from_layers(&[BASE, overlay]).unwrap().resolve(dir);
+    assert_eq!(cfg.build.fetch_concurrency, 64);
+    assert_eq!(cfg.build.encode_concurrency, 1);
+}
+
+#[test]
+fn themes_can_be_given_at_runtime() {
+    use engine::{Processor, ThemeSet};
+    let dir = std::path::Path::new(".");
+    let cfg = || {
+        Config::from_layers(&[BASE, "theme = \"Runtime\""])
+            .unwrap()
+            .resolve(dir)
+    };
+    assert!(Processor::new(cfg()).is_err());
+
+    let bytes = include_bytes!("../themes/Monokai.tmTheme");
+    let theme = ThemeSet::load_from_reader(&mut std::io::Cursor::new(&bytes[..])).unwrap();
+    let mut themes = ThemeSet::new();
+    themes.themes.insert("Runtime".to_string(), theme);
+    assert!(Processor::with_themes(cfg(), themes).is_ok());
+}
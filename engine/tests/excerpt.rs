@@ -0,0 +1,26 @@
+//! Tests for shortening excerpts of a page's plain text.
+
+use engine::truncate_excerpt;
+
+#[test]
+fn short_text_is_kept() {
+    assert_eq!(truncate_excerpt("A short  intro.", 20), "A short intro.");
+}
+
+#[test]
+fn long_text_is_cut_at_a_word_boundary() {
+    let text = "The quick brown fox jumps over the lazy dog.";
+    assert_eq!(truncate_excerpt(text, 20), "The quick brown fox…");
+    assert_eq!(truncate_excerpt(text, 18), "The quick brown…");
+    assert!(truncate_excerpt(text, 18).chars().count() <= 18);
+}
+
+#[test]
+fn long_words_are_cut() {
+    assert_eq!(truncate_excerpt("Supercalifragilistic", 6), "Super…");
+}
+
+#[test]
+fn characters_are_counted_not_bytes() {
+    assert_eq!(truncate_excerpt("héllo wörld ünïcode", 12), "héllo wörld…");
+}
@@ -0,0 +1,86 @@
+//! Tests for downloading remote assets.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use engine::{Config, EngineError, Processor};
+
+/// Serves every request with `status` on a local port,
+/// returning the port and how many requests were made.
+fn serve(status: &'static str) -> (u16, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            // skip the request line and headers
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            counter.fetch_add(1, Ordering::SeqCst);
+            let body = "not an image";
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    });
+    (port, requests)
+}
+
+#[test]
+fn client_errors_fail_without_retrying() {
+    let (port, requests) = serve("404 Not Found");
+    let dir = std::env::temp_dir().join(format!("engine-fetch-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib").join("style-chunks")).unwrap();
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("lib").join("style-chunks").join("_global.css"),
+        "body { margin: 0; }",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib").join("prelude.html"),
+        "<head>@@@SLOT_STYLES@@@</head>@@@SLOT_CONTENT@@@",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src").join("index.md"),
+        format!(
+            "---\ntitle: Home\n---\n\n![Missing](http://127.0.0.1:{}/missing.png)\n",
+            port
+        ),
+    )
+    .unwrap();
+
+    let cfg = Config::from_layers(&[
+        "[roots]\nsource = \"src\"\nlib = \"lib\"\nassets = \"assets\"\noutput = \"out\"",
+    ])
+    .unwrap()
+    .resolve(&dir);
+    let processor = Processor::new(cfg).unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    match runtime.block_on(processor.render_toplevel(false)) {
+        Err(EngineError::Failed { error, .. }) => match *error {
+            EngineError::Fetch { url, reason } => {
+                assert!(url.ends_with("/missing.png"), "{}", url);
+                assert!(reason.contains("404"), "{}", reason);
+            }
+            other => panic!("expected a fetch error, got {:?}", other),
+        },
+        other => panic!("expected a failed build, got {:?}", other),
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,575 @@
+//! Golden tests for the markdown to HTML pipeline.
+//!
+//! Every `fixtures/*.md` file is rendered with `Processor::render_markdown_str`
+//! and compared against the `.html` file next to it.
+//! Run with `UPDATE_GOLDEN=1` to (re)generate the expected output.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use engine::frontmatter::{self, Frontmatter};
+use engine::{Config, EngineError, Processor};
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+}
+
+fn processor() -> Arc<Processor> {
+    processor_with("")
+}
+
+/// Like `processor`, with extra top-level config keys.
+fn processor_with(top_level: &str) -> Arc<Processor> {
+    let cfg: Config = toml::from_str(&format!(
+        r#"
+        {}
+        [roots]
+        source = "."
+        lib = "lib"
+        assets = "assets"
+        output = "out"
+
+        [inputs]
+        extensions = ["md", "markdown"]
+        "#,
+        top_level
+    ))
+    .unwrap();
+    Processor::new(cfg.resolve(&fixtures_dir())).unwrap()
+}
+
+#[test]
+fn golden() {
+    let processor = processor();
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut inputs = std::fs::read_dir(fixtures_dir())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension() == Some("md".as_ref()))
+        .collect::<Vec<_>>();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "no fixtures found");
+
+    let mut failures = Vec::new();
+    for input in inputs {
+        let markdown = std::fs::read_to_string(&input).unwrap();
+        let actual = processor.render_markdown_str(&markdown).unwrap();
+        let expected_path = input.with_extension("html");
+        if update {
+            std::fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{:?} differs\n--- expected\n{}\n--- actual\n{}",
+                input, expected, actual
+            )),
+            Err(e) => failures.push(format!("{:?}: {}", expected_path, e)),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn frontmatter_is_extracted() {
+    let markdown = std::fs::read_to_string(fixtures_dir().join("frontmatter.md")).unwrap();
+    let (raw, body) = frontmatter::split(&markdown);
+    assert!(
+        body.trim_start().starts_with("# Testing header"),
+        "{}",
+        body
+    );
+    let frontmatter = frontmatter::parse(raw.unwrap(), &[]).expect("frontmatter should parse");
+    assert_eq!(frontmatter.title, "Testing frontmatter");
+    assert_eq!(
+        frontmatter.date,
+        Some(chrono::NaiveDate::from_ymd(2021, 4, 3))
+    );
+    assert_eq!(frontmatter.time_to_read.as_deref(), Some("5 seconds"));
+    assert_eq!(frontmatter.weight, Some(2));
+    assert_eq!(frontmatter.noindex, Some(true));
+}
+
+#[test]
+fn frontmatter_needs_a_leading_fence() {
+    // followed by text instead of a heading, with a rule in the content
+    let markdown =
+        "---\ntitle: Fenced\ndate: ~\ntime_to_read: ~\n---\nSome text\n\n---\n\nMore text\n";
+    let (raw, body) = frontmatter::split(markdown);
+    assert_eq!(raw, Some("title: Fenced\ndate: ~\ntime_to_read: ~\n"));
+    assert_eq!(body, "Some text\n\n---\n\nMore text\n");
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert!(html.contains("<p>Some text</p>"), "{}", html);
+    assert_eq!(html.matches("<hr />").count(), 1, "{}", html);
+
+    // a rule that isn't the first line doesn't start frontmatter
+    let markdown = "Intro\n\n---\ntitle: Not frontmatter\n---\n";
+    assert_eq!(frontmatter::split(markdown), (None, markdown));
+    // neither does a fence that is never closed
+    let markdown = "---\ntitle: Unclosed\n";
+    assert_eq!(frontmatter::split(markdown), (None, markdown));
+}
+
+#[test]
+fn invalid_frontmatter_reports_the_yaml_error() {
+    let markdown = "---\ntitle: [Unclosed\n---\n\nText\n";
+    assert!(frontmatter::split(markdown).0.is_some());
+    match processor().render_markdown_str(markdown) {
+        Err(EngineError::Frontmatter { reason, .. }) => {
+            assert!(!reason.contains("no frontmatter found"), "{}", reason);
+        }
+        other => panic!("expected a frontmatter error, got {:?}", other),
+    }
+}
+
+#[test]
+fn leading_rule_is_not_frontmatter() {
+    let markdown = "---\n\nIntro\n\n---\n\n# Heading\n\nText\n";
+    assert_eq!(frontmatter::split(markdown), (None, markdown));
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert_eq!(html.matches("<hr />").count(), 2, "{}", html);
+    assert!(html.contains("<p>Intro</p>"), "{}", html);
+    assert!(html.contains("Heading</h1>"), "{}", html);
+}
+
+const MISSING_ALT: &str = "---
+title: Missing alt
+date: ~
+time_to_read: ~
+---
+
+![](https://example.com/a.png) ![described](https://example.com/b.png)
+";
+
+#[test]
+fn missing_alt_text_is_allowed_by_default() {
+    assert!(processor().render_markdown_str(MISSING_ALT).is_ok());
+    let warn = processor_with(r#"accessibility-lint = "warn""#);
+    assert!(warn.render_markdown_str(MISSING_ALT).is_ok());
+}
+
+#[test]
+fn missing_alt_text_fails_strict_lint() {
+    let strict = processor_with(r#"accessibility-lint = "strict""#);
+    match strict.render_markdown_str(MISSING_ALT) {
+        Err(EngineError::Lint { reason, .. }) => {
+            assert!(reason.contains("https://example.com/a.png"), "{}", reason);
+            assert!(!reason.contains("b.png"), "{}", reason);
+        }
+        other => panic!("expected a lint error, got {:?}", other),
+    }
+}
+
+#[test]
+fn first_image_can_be_eager() {
+    let markdown = std::fs::read_to_string(fixtures_dir().join("images.md")).unwrap();
+    let html = processor_with("eager-first-image = true")
+        .render_markdown_str(&markdown)
+        .unwrap();
+    assert_eq!(html.matches(r#"<img decoding="async" "#).count(), 1);
+    assert_eq!(html.matches(r#"loading="lazy""#).count(), 1);
+    let html = processor_with("lazy-load-images = false")
+        .render_markdown_str(&markdown)
+        .unwrap();
+    assert!(!html.contains("decoding"));
+}
+
+#[test]
+fn frontmatter_defaults_are_merged() {
+    let shallow = serde_yaml::from_str("author: Root\ndescription: Shallow").unwrap();
+    let deep = serde_yaml::from_str("description: Deep\ntime_to_read: 1 minute").unwrap();
+    let frontmatter = Frontmatter::parse_with_defaults(
+        "title: Page\ndate: ~\ntime_to_read: 2 minutes",
+        &[shallow, deep],
+    )
+    .unwrap();
+    assert_eq!(frontmatter.title, "Page");
+    assert_eq!(frontmatter.author.as_deref(), Some("Root"));
+    assert_eq!(frontmatter.description.as_deref(), Some("Deep"));
+    assert_eq!(frontmatter.time_to_read.as_deref(), Some("2 minutes"));
+}
+
+#[test]
+fn emoji_shortcodes_are_expanded() {
+    let markdown = "---
+title: Emoji
+date: ~
+time_to_read: ~
+---
+
+Launch :rocket: with :white_check_mark: but not :not_an_emoji: or `:rocket:`
+
+```
+:rocket:
+```
+";
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert!(!html.contains('🚀'));
+    let html = processor_with("emoji = true")
+        .render_markdown_str(markdown)
+        .unwrap();
+    assert!(
+        html.contains("Launch 🚀 with ✅ but not :not_an_emoji:"),
+        "{}",
+        html
+    );
+    assert_eq!(html.matches(":rocket:").count(), 2, "{}", html);
+}
+
+#[test]
+fn smart_punctuation_can_be_disabled() {
+    let markdown = "---
+title: Quotes
+date: ~
+time_to_read: ~
+---
+
+\"Quoted\" -- and...
+";
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert!(html.contains("“Quoted” – and…"), "{}", html);
+    let html = processor_with("[markdown]\nsmart-punctuation = false")
+        .render_markdown_str(markdown)
+        .unwrap();
+    assert!(html.contains("&quot;Quoted&quot; -- and..."), "{}", html);
+}
+
+#[test]
+fn markdown_extensions_can_be_disabled() {
+    let markdown = "---
+title: Extensions
+date: ~
+time_to_read: ~
+---
+
+~~gone~~ <b>bold</b>
+";
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert!(html.contains("<del>gone</del> <b>bold</b>"), "{}", html);
+    let html = processor_with("[markdown]\nstrikethrough = false\nraw-html = false")
+        .render_markdown_str(markdown)
+        .unwrap();
+    assert!(
+        html.contains("~~gone~~ &lt;b&gt;bold&lt;/b&gt;"),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn raw_html_can_be_sanitized() {
+    let markdown = r#"---
+title: Sanitized
+date: ~
+time_to_read: ~
+---
+
+<div class="note" onclick="steal()">Hi <a href="javascript:steal()">there</a></div>
+
+<script>
+steal();
+</script>
+
+Inline <script>steal()</script><b>bold</b> and <a href="/ok.html">ok</a>.
+"#;
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert!(html.contains("onclick"), "{}", html);
+    let html = processor_with("[markdown]\nsanitize-html = true")
+        .render_markdown_str(markdown)
+        .unwrap();
+    assert!(!html.contains("steal"), "{}", html);
+    assert!(
+        html.contains(r#"<div class="note">Hi <a>there</a></div>"#),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains(r#"Inline <b>bold</b> and <a href="/ok.html">ok</a>."#),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn raw_html_can_be_balanced() {
+    let markdown = r#"---
+title: Unbalanced
+date: ~
+time_to_read: ~
+---
+
+<div class="note"><span>Never closed</div>
+
+Some <em>text</b>.
+
+<section>
+"#;
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert!(html.contains("<span>Never closed</div>"), "{}", html);
+    let html = processor_with("[markdown]\nbalance-html = true")
+        .render_markdown_str(markdown)
+        .unwrap();
+    assert!(
+        html.contains(r#"<div class="note"><span>Never closed</span></div>"#),
+        "{}",
+        html
+    );
+    assert!(html.contains("<p>Some <em>text.</p>"), "{}", html);
+    assert!(html.trim_end().ends_with("</section></em>"), "{}", html);
+}
+
+/// Source of the first image in some rendered HTML.
+fn image_src(html: &str) -> &str {
+    let start = html.find(r#"src=""#).expect("no image") + 5;
+    &html[start..start + html[start..].find('"').unwrap()]
+}
+
+/// Renders a page with the given body.
+fn render_body(processor: &Processor, markdown: &str) -> String {
+    let markdown = format!(
+        "---\ntitle: Images\ndate: ~\ntime_to_read: ~\n---\n\n{}",
+        markdown
+    );
+    processor.render_markdown_str(&markdown).unwrap()
+}
+
+/// Source that an image in the fixtures folder is rewritten to.
+fn processed_image_src(processor: &Processor, path: &str) -> String {
+    let file_url =
+        url::Url::from_file_path(fixtures_dir().join(path).canonicalize().unwrap()).unwrap();
+    let html = render_body(processor, &format!("![An image]({})", file_url));
+    let src = image_src(&html).to_string();
+    assert!(src.starts_with("/images/"), "{}", src);
+    src
+}
+
+#[test]
+fn relative_images_are_resolved() {
+    let processor = processor();
+    let expected = processed_image_src(&processor, "pixel.png");
+    for path in &[
+        "pixel.png",
+        "./pixel.png",
+        "/pixel.png",
+        "../fixtures/pixel.png",
+    ] {
+        let html = render_body(&processor, &format!("![A pixel]({})", path));
+        assert_eq!(image_src(&html), expected, "{}", path);
+    }
+    let html = render_body(&processor, "![Missing](missing.png)");
+    assert_eq!(image_src(&html), "missing.png");
+}
+
+#[test]
+fn relative_images_fall_back_to_the_images_root() {
+    let processor = processor();
+    let html = render_body(&processor, "![Logo](logo.png)");
+    assert_eq!(
+        image_src(&html),
+        processed_image_src(&processor, "assets/images/logo.png")
+    );
+    // next to the page wins over the images root
+    let html = render_body(&processor, "![A pixel](pixel.png)");
+    assert_eq!(
+        image_src(&html),
+        processed_image_src(&processor, "pixel.png")
+    );
+    // absolute paths are only looked up in the source root
+    let html = render_body(&processor, "![Logo](/logo.png)");
+    assert_eq!(image_src(&html), "/logo.png");
+}
+
+#[test]
+fn pretty_urls_link_to_folders() {
+    let markdown = std::fs::read_to_string(fixtures_dir().join("hyperref.md")).unwrap();
+    let html = processor_with("[outputs]\npretty-urls = true")
+        .render_markdown_str(&markdown)
+        .unwrap();
+    assert!(html.contains(r#"<a href="/toc/">Relative</a>"#), "{}", html);
+    assert!(
+        html.contains(r#"<a href="/code/">Absolute</a>"#),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains(r#"<a href="/frontmatter/">With extension</a>"#),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn more_marker_is_not_shown_as_text() {
+    let markdown =
+        "---\ntitle: More\ndate: ~\ntime_to_read: ~\n---\n\nIntro.\n\n<!-- more -->\n\nRest.\n";
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert!(html.contains("<!-- more -->"), "{}", html);
+    let html = processor_with("[markdown]\nraw-html = false")
+        .render_markdown_str(markdown)
+        .unwrap();
+    assert!(!html.contains("more"), "{}", html);
+}
+
+#[test]
+fn code_blocks_can_use_another_theme() {
+    let markdown = "```rust theme=visual-studio-code-dark\nfn main() {}\n```\n\n```rust\nfn main() {}\n```\n\n```rust theme=nope\nfn main() {}\n```\n";
+    let html = processor().render_markdown_str(markdown).unwrap();
+    let backgrounds = html
+        .match_indices(r#"<pre style="background-color:"#)
+        .map(|(i, m)| &html[i + m.len()..i + m.len() + 7])
+        .collect::<Vec<_>>();
+    assert_eq!(backgrounds.len(), 3, "{}", html);
+    assert_ne!(backgrounds[0], backgrounds[1], "{}", html);
+    // unknown themes fall back to the default one
+    assert_eq!(backgrounds[1], backgrounds[2], "{}", html);
+    assert_eq!(html.matches(r#"<code class="language-rust">"#).count(), 3);
+    assert!(!html.contains("data-theme"), "{}", html);
+}
+
+#[test]
+fn code_can_be_highlighted_with_classes() {
+    let markdown =
+        "```rust\nfn main() {}\n```\n\n```rust theme=visual-studio-code-dark\nfn main() {}\n```\n";
+    let html = processor_with(r#"highlight-style = "classes""#)
+        .render_markdown_str(markdown)
+        .unwrap();
+    assert!(!html.contains("style="), "{}", html);
+    assert!(
+        html.contains(
+            r#"<pre class="syntax-monokai"><code class="language-rust"><span class="source rust">"#
+        ),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains(r#"<pre class="syntax-visual-studio-code-dark">"#),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn escaped_characters_in_code_round_trip() {
+    let markdown = "```rust\nif a && b { x < y } else { '&lt;' }\n```\n";
+    let tags = regex::Regex::new("<[^>]*>").unwrap();
+    for style in &["inline", "classes"] {
+        let html = processor_with(&format!("highlight-style = {:?}", style))
+            .render_markdown_str(markdown)
+            .unwrap();
+        let text = tags.replace_all(&html, "");
+        assert!(
+            text.contains("if a &amp;&amp; b { x &lt; y } else { &#39;&amp;lt;&#39; }"),
+            "{}",
+            html
+        );
+    }
+}
+
+#[test]
+fn code_can_contain_closing_tags() {
+    let markdown =
+        "```html\n<pre><code>x</code></pre>\n```\n\nAfter\n\n    indented </code></pre>\n";
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert_eq!(html.matches("</code></pre>").count(), 2, "{}", html);
+    assert!(html.contains("&lt;/code&gt;&lt;/pre&gt;"), "{}", html);
+    assert!(html.contains("<p>After</p>"), "{}", html);
+    assert!(
+        html.contains(
+            r#"<code class="language-none"><span style="color:#f8f8f2;">indented &lt;/code&gt;"#
+        ),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn heading_ids_come_from_their_text() {
+    let markdown = "## Using `foo` & *bar*\n\n## With `code` {#custom}\n\n## Ends in `code`\n";
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert!(
+        html.contains(r#"<h2 id="using-foo--bar">Using <code>foo</code> &amp; <em>bar</em></h2>"#),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains(r#"<h2 id="custom">With <code>code</code></h2>"#),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains(r##"<a href="#using-foo--bar">Using foo &amp; bar</a>"##),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains(r##"<a href="#ends-in-code">Ends in code</a>"##),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn heading_slugs_can_be_transliterated() {
+    let markdown = "## Café Noté\n\n## 北京\n";
+    let html = processor().render_markdown_str(markdown).unwrap();
+    assert!(html.contains(r#"<h2 id="café-noté">"#), "{}", html);
+    assert!(html.contains(r#"<h2 id="北京">"#), "{}", html);
+
+    let html = processor_with("slug-transliterate = true")
+        .render_markdown_str(markdown)
+        .unwrap();
+    assert!(
+        html.contains(r#"<h2 id="cafe-note">Café Noté</h2>"#),
+        "{}",
+        html
+    );
+    assert!(html.contains(r#"<h2 id="bei-jing">北京</h2>"#), "{}", html);
+}
+
+#[test]
+fn only_images_on_their_own_become_figures() {
+    let html = render_body(
+        &processor(),
+        "![A cat](https://example.com/cat.png \"My <cat>\")\n\nAn inline ![dog](https://example.com/dog.png \"Not a caption\") image.\n",
+    );
+    assert!(html.starts_with("<figure><img "), "{}", html);
+    assert!(
+        html.contains(r#"alt="A cat" /><figcaption>My &lt;cat&gt;</figcaption></figure>"#),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains(r#"alt="dog" title="Not a caption" />"#),
+        "{}",
+        html
+    );
+    assert_eq!(html.matches("<figure>").count(), 1);
+}
+
+#[test]
+fn images_can_have_a_fallback() {
+    let markdown =
+        "![Photo](https://example.com/photo.JPG)\n\n![Logo](https://example.com/logo.svg)\n";
+    let html = render_body(&processor(), markdown);
+    assert!(!html.contains("<picture>"), "{}", html);
+
+    let html = render_body(&processor_with("image-fallback = true"), markdown);
+    assert_eq!(
+        html.matches("<picture><source srcset=\"/images/").count(),
+        2
+    );
+    assert_eq!(html.matches(".webp\" type=\"image/webp\"><img ").count(), 2);
+    assert!(
+        html.contains(".jpg\" alt=\"Photo\" /></picture>"),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains(".png\" alt=\"Logo\" /></picture>"),
+        "{}",
+        html
+    );
+}
@@ -0,0 +1,53 @@
+//! Tests for building local images.
+
+use std::path::Path;
+
+use engine::{Config, Processor};
+
+#[test]
+fn local_image_fallbacks_are_built_into_an_empty_output() {
+    let dir = std::env::temp_dir().join(format!("engine-images-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib").join("style-chunks")).unwrap();
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("lib").join("style-chunks").join("_global.css"),
+        "body { margin: 0; }",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib").join("prelude.html"),
+        "<head>@@@SLOT_STYLES@@@@@@SLOT_HEAD_SCRIPTS@@@</head>@@@SLOT_CONTENT@@@",
+    )
+    .unwrap();
+    let pixel = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/pixel.png");
+    std::fs::copy(pixel, dir.join("src").join("pixel.png")).unwrap();
+    std::fs::write(
+        dir.join("src").join("index.md"),
+        "---\ntitle: Home\n---\n\n![A pixel](pixel.png)\n",
+    )
+    .unwrap();
+
+    let cfg = Config::from_layers(&["image-fallback = true\n\
+         [roots]\nsource = \"src\"\nlib = \"lib\"\nassets = \"assets\"\noutput = \"out\""])
+    .unwrap()
+    .resolve(&dir);
+    let processor = Processor::new(cfg).unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let summary = runtime
+        .block_on(processor.clone().render_toplevel(false))
+        .unwrap();
+
+    // the WebP version and the PNG fallback
+    assert_eq!(summary.images.written, 2, "{:?}", summary);
+    let images = std::fs::read_dir(dir.join("out").join("images"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect::<Vec<_>>();
+    assert!(
+        images.iter().any(|p| p.extension().unwrap() == "png"),
+        "{:?}",
+        images
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,26 @@
+//! Tests for working out the text direction of a page's language.
+
+use engine::text_direction;
+
+#[test]
+fn most_languages_are_left_to_right() {
+    for lang in &["en", "en-US", "fr", "zh-Hant", "ja"] {
+        assert_eq!(text_direction(lang), "ltr", "{}", lang);
+    }
+}
+
+#[test]
+fn some_languages_are_right_to_left() {
+    for lang in &["ar", "ar-EG", "he", "fa-IR", "ur", "YI"] {
+        assert_eq!(text_direction(lang), "rtl", "{}", lang);
+    }
+}
+
+#[test]
+fn the_script_decides_if_given() {
+    assert_eq!(text_direction("ks-Deva"), "ltr");
+    assert_eq!(text_direction("ks-Arab"), "rtl");
+    assert_eq!(text_direction("pa-Arab-PK"), "rtl");
+    assert_eq!(text_direction("az_Arab"), "rtl");
+    assert_eq!(text_direction("az-Latn"), "ltr");
+}
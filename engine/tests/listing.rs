@@ -0,0 +1,123 @@
+//! Tests for pages listing other pages.
+
+use std::path::{Path, PathBuf};
+
+use engine::{BuildSummary, Config, Processor};
+
+fn site(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("engine-listing-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib").join("style-chunks")).unwrap();
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("lib").join("style-chunks").join("_global.css"),
+        "body { margin: 0; }",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib").join("prelude.html"),
+        "<head>@@@SLOT_STYLES@@@@@@SLOT_HEAD_SCRIPTS@@@</head>@@@SLOT_CONTENT@@@",
+    )
+    .unwrap();
+    dir
+}
+
+fn build(dir: &Path) -> BuildSummary {
+    let cfg = Config::from_layers(&[
+        "[roots]\nsource = \"src\"\nlib = \"lib\"\nassets = \"assets\"\noutput = \"out\"\n\
+         [outputs]\ngenerate-search-index = true",
+    ])
+    .unwrap()
+    .resolve(dir);
+    let processor = Processor::new(cfg).unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(processor.render_toplevel(false)).unwrap()
+}
+
+/// Builds the site and returns its search index
+fn search_index(dir: &Path) -> String {
+    build(dir);
+    std::fs::read_to_string(dir.join("out").join("search-index.json")).unwrap()
+}
+
+#[test]
+fn children_slot_is_not_page_text() {
+    let dir = site("children");
+    std::fs::create_dir_all(dir.join("src").join("notes")).unwrap();
+    std::fs::write(
+        dir.join("src").join("index.md"),
+        "---\ntitle: Home\n---\n\n[Notes](hyperref:notes/index)\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src").join("notes").join("index.md"),
+        "---\ntitle: Notes\nlist_children: true\n---\n\n@@@SLOT_CHILDREN@@@\n\nAll my notes.\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src").join("notes").join("a.md"),
+        "---\ntitle: A\n---\n\nNothing here.\n",
+    )
+    .unwrap();
+
+    let index = search_index(&dir);
+    assert!(!index.contains("@@@SLOT_CHILDREN@@@"), "{}", index);
+    assert!(
+        index.contains(r#""excerpt":"All my notes.","content":"All my notes.""#),
+        "{}",
+        index
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn post_list_slot_is_not_page_text() {
+    let dir = site("posts");
+    std::fs::write(
+        dir.join("src").join("index.md"),
+        "---\ntitle: Home\n---\n\nHi, see [a](hyperref:a).\n\n@@@SLOT_POST_LIST@@@\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src").join("a.md"),
+        "---\ntitle: A\ndate: 04/03/2021\n---\n\nNothing here.\n",
+    )
+    .unwrap();
+
+    let index = search_index(&dir);
+    assert!(!index.contains("@@@SLOT_POST_LIST@@@"), "{}", index);
+    assert!(index.contains(r#""content":"Hi, see a.""#), "{}", index);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn undated_pages_with_page_nav_stay_fresh() {
+    let dir = site("nav");
+    std::fs::write(
+        dir.join("lib").join("prelude.html"),
+        "<head>@@@SLOT_STYLES@@@@@@SLOT_HEAD_SCRIPTS@@@</head>@@@SLOT_CONTENT@@@\
+         <!-- @@@IF_PREV_URL@@@ --><a href=\"@@@SLOT_PREV_URL@@@\">Previous</a><!-- @@@ENDIF@@@ -->\
+         <!-- @@@IF_NEXT_URL@@@ --><a href=\"@@@SLOT_NEXT_URL@@@\">Next</a><!-- @@@ENDIF@@@ -->",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src").join("index.md"),
+        "---\ntitle: Home\n---\n\nHi.\n",
+    )
+    .unwrap();
+
+    let first = build(&dir);
+    assert_eq!(first.pages.written, 1, "{:?}", first);
+    // only the modification times can tell the output is fresh now
+    let out = dir.join("out");
+    std::fs::remove_file(out.join(".build-cache")).unwrap();
+    std::fs::write(out.join("index.html"), "edited").unwrap();
+    let second = build(&dir);
+    assert_eq!(second.pages.written, 0, "{:?}", second);
+    assert_eq!(second.pages.fresh, 1, "{:?}", second);
+    assert_eq!(
+        std::fs::read_to_string(out.join("index.html")).unwrap(),
+        "edited"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,52 @@
+//! Tests for removing the CSS rules a page doesn't use.
+
+use engine::purge::{purge, UsedSelectors};
+
+const HTML: &str = r#"<html><body><nav class="top menu"><a id=home href="/">Home</a></nav><p class='note'>Hi</p></body></html>"#;
+
+fn used(safelist: &[&str]) -> UsedSelectors {
+    let safelist = safelist.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    UsedSelectors::from_html(HTML, &safelist)
+}
+
+#[test]
+fn unused_rules_are_removed() {
+    let css = "p{color:red}table{width:100%}.note{margin:0}.warning{color:orange}#home{font-weight:bold}#away{display:none}";
+    assert_eq!(
+        purge(css, &used(&[])),
+        "p{color:red}.note{margin:0}#home{font-weight:bold}"
+    );
+}
+
+#[test]
+fn selectors_are_filtered_within_a_list() {
+    let css = "nav a, footer a, .menu:hover, .note::before{color:blue}";
+    assert_eq!(
+        purge(css, &used(&[])),
+        "nav a,.menu:hover,.note::before{color:blue}"
+    );
+}
+
+#[test]
+fn media_queries_are_purged_and_other_at_rules_kept() {
+    let css = "@import url(\"a.css\");@media (max-width: 600px){table{width:auto}}@media print{nav{display:none}}@font-face{font-family:X}@keyframes spin{from{opacity:0}}";
+    assert_eq!(
+        purge(css, &used(&[])),
+        "@import url(\"a.css\");@media print{nav{display:none}}@font-face{font-family:X}@keyframes spin{from{opacity:0}}"
+    );
+}
+
+#[test]
+fn safelisted_names_are_kept() {
+    let css = ".dark{color:white}.js-open{display:block}.other{color:red}";
+    assert_eq!(
+        purge(css, &used(&["dark", "js-*"])),
+        ".dark{color:white}.js-open{display:block}"
+    );
+}
+
+#[test]
+fn comments_are_removed() {
+    let css = "/* { not a rule } */p{content:\"}\"}";
+    assert_eq!(purge(css, &used(&[])), "p{content:\"}\"}");
+}
@@ -0,0 +1,27 @@
+//! Tests for the reading statistics of a page's plain text.
+
+use engine::ReadingStats;
+
+#[test]
+fn words_and_characters_are_counted() {
+    let stats = ReadingStats::of("Hello,  world!\nThis is\ttext.");
+    assert_eq!(stats.words, 5);
+    assert_eq!(stats.characters, 23);
+}
+
+#[test]
+fn empty_text_has_no_words() {
+    assert_eq!(ReadingStats::of("  \n "), ReadingStats::default());
+}
+
+#[test]
+fn minutes_are_rounded_up_to_at_least_one() {
+    let words = |n| ReadingStats {
+        words: n,
+        characters: 0,
+    };
+    assert_eq!(words(0).minutes(200), 1);
+    assert_eq!(words(200).minutes(200), 1);
+    assert_eq!(words(201).minutes(200), 2);
+    assert_eq!(words(1000).minutes(100), 10);
+}
@@ -0,0 +1,110 @@
+//! Tests for the prelude's conditional blocks and slots.
+
+use engine::expand_frontmatter_blocks;
+use engine::frontmatter::Frontmatter;
+use engine::{Config, Processor};
+
+const TEMPLATE: &str = "<h1>title</h1>
+<!-- @@@IF featured @@@ -->
+<p>Featured</p>
+<!-- @@@ENDIF@@@ -->
+<!-- @@@IF author @@@ --><p>By someone</p><!-- @@@ENDIF@@@ -->";
+
+fn frontmatter(yaml: &str) -> Frontmatter {
+    Frontmatter::parse_from_str(yaml).unwrap()
+}
+
+#[test]
+fn blocks_are_kept_for_truthy_keys() {
+    let html = expand_frontmatter_blocks(
+        TEMPLATE,
+        &frontmatter("title: Hi\nauthor: Me\nfeatured: true"),
+    );
+    assert_eq!(
+        html,
+        "<h1>title</h1>\n\n<p>Featured</p>\n\n<p>By someone</p>"
+    );
+}
+
+#[test]
+fn blocks_are_removed_for_missing_or_falsy_keys() {
+    for yaml in &[
+        "title: Hi",
+        "title: Hi\nfeatured: false",
+        "title: Hi\nfeatured: ''",
+        "title: Hi\nfeatured: []",
+        "title: Hi\nfeatured: ~",
+    ] {
+        let html = expand_frontmatter_blocks(TEMPLATE, &frontmatter(yaml));
+        assert_eq!(html, "<h1>title</h1>\n\n", "{}", yaml);
+    }
+}
+
+#[test]
+fn negated_blocks_are_kept_for_missing_keys() {
+    let template = "<!-- @@@IFNOT date @@@ -->Undated<!-- @@@ENDIF@@@ -->";
+    let html = expand_frontmatter_blocks(template, &frontmatter("title: Hi"));
+    assert_eq!(html, "Undated");
+    let html = expand_frontmatter_blocks(template, &frontmatter("title: Hi\ndate: 01/02/2021"));
+    assert_eq!(html, "");
+}
+
+#[test]
+fn else_parts_are_kept_instead() {
+    let template =
+        "<!-- @@@IF author @@@ -->By someone<!-- @@@ELSE@@@ -->Anonymous<!-- @@@ENDIF@@@ -->
+<!-- @@@IFNOT author @@@ -->Anonymous<!-- @@@ELSE@@@ -->By someone<!-- @@@ENDIF@@@ -->";
+    let html = expand_frontmatter_blocks(template, &frontmatter("title: Hi\nauthor: Me"));
+    assert_eq!(html, "By someone\nBy someone");
+    let html = expand_frontmatter_blocks(template, &frontmatter("title: Hi"));
+    assert_eq!(html, "Anonymous\nAnonymous");
+}
+
+#[test]
+fn frontmatter_slots_are_escaped() {
+    let dir = std::env::temp_dir().join(format!("engine-template-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib").join("style-chunks")).unwrap();
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("lib").join("style-chunks").join("_global.css"),
+        "body { margin: 0; }",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib").join("prelude.html"),
+        "<head>@@@SLOT_STYLES@@@@@@SLOT_HEAD_SCRIPTS@@@\
+         <meta property=\"og:title\" content=\"@@@SLOT_TITLE@@@\">\
+         <!-- @@@IF_DESCRIPTION@@@ -->\
+         <meta property=\"og:description\" content=\"@@@SLOT_DESCRIPTION@@@\">\
+         <!-- @@@ENDIF@@@ --></head>@@@SLOT_CONTENT@@@",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src").join("index.md"),
+        "---\ntitle: 'The \"best\" <post> & more'\ndescription: 'Say \"hi\" <b> & bye'\n---\n\nText.\n",
+    )
+    .unwrap();
+
+    let cfg = Config::from_layers(&[
+        "[roots]\nsource = \"src\"\nlib = \"lib\"\nassets = \"assets\"\noutput = \"out\"",
+    ])
+    .unwrap()
+    .resolve(&dir);
+    let processor = Processor::new(cfg).unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(processor.render_toplevel(false)).unwrap();
+
+    let html = std::fs::read_to_string(dir.join("out").join("index.html")).unwrap();
+    assert!(
+        html.contains(r#"content="The &quot;best&quot; &lt;post&gt; &amp; more""#),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains(r#"content="Say &quot;hi&quot; &lt;b&gt; &amp; bye""#),
+        "{}",
+        html
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
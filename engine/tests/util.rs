@@ -0,0 +1,120 @@
+//! Tests for the path helpers.
+
+use std::path::Path;
+
+use engine::util::{copy_dir_keeping_times, to_native_path, to_url_path, PathHelper};
+
+#[test]
+fn prefix_is_removed_from_absolute_paths() {
+    let path = Path::new("/site/out/css/_global.css");
+    assert_eq!(
+        path.maybe_unprefix(Path::new("/site/out")),
+        Path::new("css/_global.css")
+    );
+    assert_eq!(
+        path.maybe_unprefix(Path::new("/site/out/")),
+        Path::new("css/_global.css")
+    );
+}
+
+#[test]
+fn prefix_is_removed_from_relative_paths() {
+    let path = Path::new("out/blog/post.html");
+    assert_eq!(
+        path.maybe_unprefix(Path::new("out")),
+        Path::new("blog/post.html")
+    );
+}
+
+#[test]
+fn paths_without_the_prefix_are_unchanged() {
+    let path = Path::new("/site/output/index.html");
+    assert_eq!(path.maybe_unprefix(Path::new("/site/out")), path);
+    assert_eq!(path.maybe_unprefix(Path::new("site")), path);
+    let path = Path::new("out/index.html");
+    assert_eq!(path.maybe_unprefix(Path::new("/out")), path);
+}
+
+#[test]
+fn url_paths_use_forward_slashes() {
+    assert_eq!(
+        to_url_path(Path::new("css/blog/code.css")),
+        "css/blog/code.css"
+    );
+    assert_eq!(
+        to_url_path(Path::new("./blog/../post.html")),
+        "blog/../post.html"
+    );
+    assert_eq!(to_url_path(Path::new("/index.html")), "/index.html");
+    assert_eq!(to_url_path(Path::new("")), "");
+}
+
+#[cfg(unix)]
+#[test]
+fn native_paths_are_unchanged_on_unix() {
+    // backslashes are part of file names here
+    assert_eq!(
+        to_native_path(Path::new(r"/site/src/a\b.md")),
+        Path::new(r"/site/src/a\b.md")
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_paths_are_normalized() {
+    assert_eq!(
+        to_url_path(Path::new(r"css\blog\code.css")),
+        "css/blog/code.css"
+    );
+    assert_eq!(
+        to_native_path(Path::new(r"\\?\C:\site\src/blog/post.md")),
+        Path::new(r"\\?\C:\site\src\blog\post.md")
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn prefix_is_removed_from_windows_paths() {
+    let path = Path::new(r"C:\site\out\css\_global.css");
+    assert_eq!(
+        path.maybe_unprefix(Path::new(r"C:\site\out")),
+        Path::new(r"css\_global.css")
+    );
+    let path = Path::new(r"\\?\C:\site\out\index.html");
+    assert_eq!(
+        path.maybe_unprefix(Path::new(r"\\?\C:\site\out")),
+        Path::new("index.html")
+    );
+    assert_eq!(path.maybe_unprefix(Path::new(r"D:\site\out")), path);
+}
+
+#[test]
+fn copied_folders_keep_modification_times() {
+    let dir = std::env::temp_dir().join(format!("engine-copy-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let from = dir.join("out");
+    std::fs::create_dir_all(from.join("css")).unwrap();
+    std::fs::write(from.join("index.html"), "<p>hi</p>").unwrap();
+    std::fs::write(from.join("css").join("site.css"), "p{}").unwrap();
+    let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    std::fs::File::options()
+        .write(true)
+        .open(from.join("css").join("site.css"))
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+
+    let to = dir.join(".out.staging");
+    copy_dir_keeping_times(&from, &to).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(to.join("index.html")).unwrap(),
+        "<p>hi</p>"
+    );
+    let modified = |p: &Path| std::fs::metadata(p).unwrap().modified().unwrap();
+    assert_eq!(modified(&to.join("css").join("site.css")), old);
+    assert_eq!(
+        modified(&to.join("index.html")),
+        modified(&from.join("index.html"))
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}